@@ -1,52 +1,188 @@
+use cgmath::InnerSpace;
+use rand::distr::uniform::SampleUniform;
 use rand::Rng;
+use std::hash::Hasher;
+use std::f64::consts::PI;
 use std::ops::Range;
 
+use crate::force::*;
+use crate::hash::*;
+use crate::material::*;
 use crate::maths::*;
 
 pub const GRAVITY: real = 1000.0;
 
 #[derive(Copy, Clone)]
-pub struct Ball
+pub struct Ball<F: Float = real>
 {
-    pub location: Vec2,
-    old_pos: Vec2,
-    pub radius: real,
-    pub colour: Colour
+    pub location: Vec2<F>,
+    old_pos: Vec2<F>,
+    pub radius: F,
+    pub colour: Colour,
+    pub material: Material,
+    // Explicit mass, None derives it from area and density
+    pub mass: Option<F>,
+    // Fixed balls are never moved by the solver
+    pub fixed: bool,
+    // Overrides the world's drag, None uses the world's
+    pub drag: Option<Drag>,
+    // Sleeping balls aren't integrated until something wakes them
+    asleep: bool,
+    // Steps in a row spent moving slower than the sleep velocity
+    still_steps: u32,
+    // Where the ball was when still steps were last counted
+    still_pos: Vec2<F>
 }
 
-impl Ball
+impl<F: Float> Ball<F>
 {
-    pub fn verlet(&mut self, dt: real)
+    pub fn verlet(&mut self, dt: F, acceleration: Vec2<F>, drag: &Drag)
     {
-        let vel = self.velocity();
+        if self.fixed || self.asleep { return; }
+        
+        let vel = self.drag.as_ref().unwrap_or(drag).apply(self.velocity(), dt);
         self.old_pos = self.location;
-        self.location += vel - Vec2::new(0.0, GRAVITY * dt * dt);
+        self.location += vel + (acceleration * dt * dt);
     }
-    pub fn velocity(&self) -> Vec2
+    pub fn velocity(&self) -> Vec2<F>
     {
         return self.location - self.old_pos;
     }
+    pub fn set_velocity(&mut self, vel: Vec2<F>)
+    {
+        self.old_pos = self.location - vel;
+    }
+    // Where the ball was last step. Velocity is how far it has moved since.
+    pub fn old_pos(&self) -> Vec2<F>
+    {
+        return self.old_pos;
+    }
+    pub fn set_old_pos(&mut self, p: Vec2<F>)
+    {
+        self.old_pos = p;
+    }
+    // Moves the ball without changing its velocity
+    pub fn translate(&mut self, d: Vec2<F>)
+    {
+        self.location += d;
+        self.old_pos += d;
+        self.still_pos += d;
+    }
+    pub fn mass(&self) -> F
+    {
+        return self.mass.unwrap_or(F::of(PI) * self.radius * self.radius * F::of(self.material.density.into()));
+    }
+    pub fn inv_mass(&self) -> F
+    {
+        if self.fixed { return F::zero(); }
+        
+        return self.mass().recip();
+    }
     
-    pub fn new_location(b: Ball, l: Vec2) -> Ball
+    pub fn is_asleep(&self) -> bool
+    {
+        return self.asleep;
+    }
+    pub fn still_steps(&self) -> u32
+    {
+        return self.still_steps;
+    }
+    // Adds a still step if the ball moved less than max_dist since the last
+    // count, otherwise starts counting again. Uses where the ball ends up
+    // rather than its velocity, as balls squashed in a pile get pushed back
+    // and forth by the solver every step without going anywhere.
+    pub fn count_still(&mut self, max_dist: F)
+    {
+        if self.asleep { return; }
+        
+        let moved = self.location - self.still_pos;
+        self.still_pos = self.location;
+        self.still_steps = if moved.magnitude2() <= max_dist * max_dist
+            { self.still_steps.saturating_add(1) } else { 0 };
+    }
+    // Stops the ball dead until it is woken
+    pub fn sleep(&mut self)
+    {
+        self.asleep = true;
+        self.old_pos = self.location;
+    }
+    pub fn wake(&mut self)
+    {
+        if !self.asleep { return; }
+        
+        self.asleep = false;
+        self.still_steps = 0;
+    }
+    
+    // Feeds everything that affects how the ball moves into the hasher
+    pub fn hash_state(&self, h: &mut StateHasher)
+    {
+        h.vec2(self.location);
+        h.vec2(self.old_pos);
+        h.real(self.radius);
+        h.real(self.material.restitution);
+        h.real(self.material.friction);
+        h.real(self.material.density);
+        h.real(self.mass.unwrap_or(-F::one()));
+        h.write_u8(self.fixed as u8);
+        match self.drag
+        {
+            Some(d) =>
+            {
+                h.write_u8(1);
+                h.real(d.linear);
+                h.real(d.quadratic);
+                h.real(d.damping);
+            }
+            None => h.write_u8(0)
+        }
+        h.write_u8(self.asleep as u8);
+        h.write_u32(self.still_steps);
+        h.vec2(self.still_pos);
+    }
+    
+    pub fn new_location(b: Ball<F>, l: Vec2<F>) -> Ball<F>
     {
         return Ball {
             location: l,
             old_pos: b.old_pos,
             radius: b.radius,
-            colour: b.colour
+            colour: b.colour,
+            material: b.material,
+            mass: b.mass,
+            fixed: b.fixed,
+            drag: b.drag,
+            asleep: b.asleep,
+            still_steps: b.still_steps,
+            still_pos: b.still_pos
+        };
+    }
+    pub fn new_material(b: Ball<F>, m: Material) -> Ball<F>
+    {
+        return Ball {
+            material: m,
+            ..b
         };
     }
     
-    pub fn new(l: Vec2, r: real, c: Colour) -> Ball
+    pub fn new(l: Vec2<F>, r: F, c: Colour) -> Ball<F>
     {
         return Ball {
             location: l,
             old_pos: l,
             radius: r,
-            colour: c
+            colour: c,
+            material: Material::DEFAULT,
+            mass: None,
+            fixed: false,
+            drag: None,
+            asleep: false,
+            still_steps: 0,
+            still_pos: l
         };
     }
-    pub fn random<T: Rng>(rng: &mut T, pos: &Range<Vec2>, radius: Range<real>) -> Ball
+    pub fn random<T: Rng>(rng: &mut T, pos: &Range<Vec2<F>>, radius: Range<F>) -> Ball<F>
+        where F: SampleUniform
     {
         let x = rng.random_range(pos.start.x..pos.end.x);
         let y = rng.random_range(pos.start.y..pos.end.y);
@@ -0,0 +1,88 @@
+use cgmath::InnerSpace;
+
+use crate::ball::*;
+use crate::handle::*;
+use crate::maths::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LinkKind
+{
+    // Holds the balls exactly length apart
+    Stick,
+    // Pulls towards length by stiffness (0 to 1) of the error each substep,
+    // with damping (0 to 1) taking out that share of the balls' relative
+    // speed along the link. Both are spread over the solver's passes, so
+    // don't depend on how many there are.
+    Spring { stiffness: real, damping: real },
+    // Only stops the balls getting further than length apart
+    Rope
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Link<F: Float = real>
+{
+    pub a: BallHandle,
+    pub b: BallHandle,
+    pub length: F,
+    pub kind: LinkKind
+}
+
+impl<F: Float> Link<F>
+{
+    pub fn new(a: BallHandle, b: BallHandle, length: F, kind: LinkKind) -> Link<F>
+    {
+        return Link {
+            a,
+            b,
+            length,
+            kind
+        };
+    }
+
+    pub fn joins(&self, a: BallHandle, b: BallHandle) -> bool
+    {
+        return (self.a == a && self.b == b) || (self.a == b && self.b == a);
+    }
+
+    pub fn touches(&self, h: BallHandle) -> bool
+    {
+        return self.a == h || self.b == h;
+    }
+
+    // Called passes times each substep
+    pub fn solve(&self, a: &mut Ball<F>, b: &mut Ball<F>, passes: usize)
+    {
+        let axis = b.location - a.location;
+        let dist = axis.magnitude();
+        if dist == F::zero() { return; }
+
+        let inv_a = a.inv_mass();
+        let inv_b = b.inv_mass();
+        let inv_sum = inv_a + inv_b;
+        if inv_sum == F::zero() { return; }
+
+        let axis = axis / dist;
+        let diff = dist - self.length;
+        let correction = match self.kind
+        {
+            LinkKind::Stick => diff,
+            LinkKind::Rope => diff.max(F::zero()),
+            LinkKind::Spring { stiffness, damping } =>
+            {
+                let speed = (b.velocity() - a.velocity()).dot(axis);
+                (diff * per_pass(stiffness, passes)) + (speed * per_pass(damping, passes))
+            }
+        };
+
+        let offset = axis * (correction / inv_sum);
+        a.location += offset * inv_a;
+        b.location -= offset * inv_b;
+    }
+}
+
+// The share to take each pass so that passes of them take share in all
+fn per_pass<F: Float>(share: real, passes: usize) -> F
+{
+    let left = (1.0 - share.clamp(0.0, 1.0) as f64).powf(1.0 / passes as f64);
+    return F::of(1.0 - left);
+}
@@ -0,0 +1,159 @@
+use cgmath::InnerSpace;
+use std::f64::consts::PI;
+use std::hash::Hasher;
+
+use crate::hash::*;
+use crate::maths::*;
+use crate::obstacle::*;
+
+// Sides used when turning round containers into outlines
+const OUTLINE_SIDES: usize = 64;
+
+// The shape the balls are kept inside of
+#[derive(Clone, Debug)]
+pub enum Container<F: Float = real>
+{
+    // The physics bounds rectangle, which follows the window
+    Bounds,
+    Circle { centre: Vec2<F>, radius: F },
+    // All points within radius of the segment from a to b
+    Capsule { a: Vec2<F>, b: Vec2<F>, radius: F },
+    // Closed loop of points, which may be concave
+    Polygon(Vec<Vec2<F>>)
+}
+
+impl<F: Float> Container<F>
+{
+    // Returns the normal pointing back inside and how far a circle at centre
+    // pokes out of the container, if it does. Bounds is handled by the caller.
+    pub fn contact(&self, centre: Vec2<F>, radius: F) -> Option<(Vec2<F>, F)>
+    {
+        return match self
+        {
+            Container::Bounds => None,
+            Container::Circle { centre: c, radius: r } => round_contact(centre, radius, *c, *r),
+            Container::Capsule { a, b, radius: r } =>
+                round_contact(centre, radius, closest_on_segment(centre, *a, *b), *r),
+            Container::Polygon(points) => polygon_contact(centre, radius, points)
+        };
+    }
+
+    // Points around the edge of the container, for drawing
+    pub fn outline(&self, bounds: Vec4<F>) -> Vec<Vec2<F>>
+    {
+        return match self
+        {
+            Container::Bounds => vec![
+                vec2(bounds.x, bounds.w), vec2(bounds.y, bounds.w),
+                vec2(bounds.y, bounds.z), vec2(bounds.x, bounds.z)],
+            Container::Circle { centre, radius } => circle_points(*centre, *radius, OUTLINE_SIDES),
+            Container::Capsule { a, b, radius } =>
+            {
+                let d = *b - *a;
+                let start = d.y.atan2(d.x) - F::of(PI * 0.5);
+                let half = OUTLINE_SIDES / 2;
+                let mut points = Vec::with_capacity(half * 2 + 2);
+                for i in 0..=half
+                {
+                    let angle = start + F::of(PI * i as f64 / half as f64);
+                    points.push(*b + rotate(vec2(*radius, F::zero()), angle));
+                }
+                for i in 0..=half
+                {
+                    let angle = start + F::of(PI) + F::of(PI * i as f64 / half as f64);
+                    points.push(*a + rotate(vec2(*radius, F::zero()), angle));
+                }
+                points
+            }
+            Container::Polygon(points) => points.clone()
+        };
+    }
+
+    pub fn hash_state(&self, h: &mut StateHasher)
+    {
+        match self
+        {
+            Container::Bounds => h.write_u8(0),
+            Container::Circle { centre, radius } =>
+            {
+                h.write_u8(1);
+                h.vec2(*centre);
+                h.real(*radius);
+            }
+            Container::Capsule { a, b, radius } =>
+            {
+                h.write_u8(2);
+                h.vec2(*a);
+                h.vec2(*b);
+                h.real(*radius);
+            }
+            Container::Polygon(points) =>
+            {
+                h.write_u8(3);
+                h.write_usize(points.len());
+                for p in points
+                {
+                    h.vec2(*p);
+                }
+            }
+        }
+    }
+}
+
+// Keeps a circle within radius r of the point c
+fn round_contact<F: Float>(centre: Vec2<F>, radius: F, c: Vec2<F>, r: F) -> Option<(Vec2<F>, F)>
+{
+    let d = centre - c;
+    let dist = d.magnitude();
+    let depth = dist + radius - r;
+    if depth <= F::zero() { return None; }
+
+    if dist == F::zero()
+    {
+        return Some((vec2(F::zero(), F::one()), depth));
+    }
+    return Some((-d / dist, depth));
+}
+
+fn polygon_contact<F: Float>(centre: Vec2<F>, radius: F, points: &[Vec2<F>]) -> Option<(Vec2<F>, F)>
+{
+    let mut inside = false;
+    let mut closest = centre;
+    let mut closest_dist = F::max_value();
+
+    for i in 0..points.len()
+    {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+
+        // Even-odd crossing test along +x
+        if (a.y > centre.y) != (b.y > centre.y)
+        {
+            let x = a.x + ((centre.y - a.y) / (b.y - a.y) * (b.x - a.x));
+            if centre.x < x
+            {
+                inside = !inside;
+            }
+        }
+
+        let p = closest_on_segment(centre, a, b);
+        let dist = (centre - p).magnitude2();
+        if dist < closest_dist
+        {
+            closest_dist = dist;
+            closest = p;
+        }
+    }
+
+    let dist = closest_dist.sqrt();
+    if inside
+    {
+        if dist >= radius || dist == F::zero() { return None; }
+        return Some(((centre - closest) / dist, radius - dist));
+    }
+    if dist == F::zero()
+    {
+        return None;
+    }
+    return Some(((closest - centre) / dist, radius + dist));
+}
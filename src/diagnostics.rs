@@ -0,0 +1,96 @@
+use std::collections::vec_deque::Iter;
+use std::collections::VecDeque;
+
+use crate::maths::*;
+
+// Totals over the world after a step, for checking whether the solver adds
+// or loses energy. Fixed balls don't count towards the energy or momentum.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Diagnostics<F: Float = real>
+{
+    // ½mv² summed over every ball, using the velocity implied by Verlet
+    pub kinetic: F,
+    // From the world's gravity, taking the origin as zero, plus from
+    // gravitation between the balls if that is on
+    pub potential: F,
+    pub momentum: Vec2<F>,
+    // Deepest overlap between two balls found by the last step
+    pub max_penetration: F,
+    pub contacts: usize
+}
+
+impl<F: Float> Diagnostics<F>
+{
+    // No energy or momentum yet, to be added up ball by ball
+    pub fn new(contacts: usize, max_penetration: F) -> Diagnostics<F>
+    {
+        return Diagnostics {
+            kinetic: F::zero(),
+            potential: F::zero(),
+            momentum: vec2(F::zero(), F::zero()),
+            max_penetration,
+            contacts
+        };
+    }
+
+    pub fn energy(&self) -> F
+    {
+        return self.kinetic + self.potential;
+    }
+}
+
+// The last few steps' diagnostics, oldest first
+pub struct History<F: Float = real>
+{
+    samples: VecDeque<Diagnostics<F>>,
+    capacity: usize
+}
+
+impl<F: Float> History<F>
+{
+    pub fn new(capacity: usize) -> History<F>
+    {
+        return History {
+            samples: VecDeque::with_capacity(capacity),
+            capacity
+        };
+    }
+
+    // Adds the newest sample, forgetting the oldest if full
+    pub fn push(&mut self, d: Diagnostics<F>)
+    {
+        if self.samples.len() == self.capacity
+        {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(d);
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.samples.clear();
+    }
+
+    pub fn len(&self) -> usize
+    {
+        return self.samples.len();
+    }
+    pub fn capacity(&self) -> usize
+    {
+        return self.capacity;
+    }
+    pub fn latest(&self) -> Option<&Diagnostics<F>>
+    {
+        return self.samples.back();
+    }
+    pub fn iter(&self) -> Iter<'_, Diagnostics<F>>
+    {
+        return self.samples.iter();
+    }
+
+    // Smallest and largest value of f over the samples
+    pub fn range<T: Float, M: Fn(&Diagnostics<F>) -> T>(&self, f: M) -> (T, T)
+    {
+        return self.samples.iter().map(f).fold((T::max_value(), T::min_value()), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    }
+}
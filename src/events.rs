@@ -0,0 +1,129 @@
+use std::mem;
+use std::vec::Drain;
+
+use crate::handle::*;
+use crate::maths::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ContactPhase
+{
+    Begin,
+    Persist,
+    End
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ContactEvent<F: Float = real>
+{
+    pub phase: ContactPhase,
+    // a is always the lower handle of the pair
+    pub a: BallHandle,
+    pub b: BallHandle,
+    // How far the balls overlapped before being pushed apart
+    pub depth: F,
+    // Unit vector pointing from b towards a
+    pub normal: Vec2<F>,
+    // Velocity of a relative to b, in units per second
+    pub relative_velocity: Vec2<F>
+}
+
+impl<F: Float> ContactEvent<F>
+{
+    pub fn new(a: BallHandle, b: BallHandle, depth: F, normal: Vec2<F>, relative_velocity: Vec2<F>) -> ContactEvent<F>
+    {
+        let (a, b, normal, relative_velocity) = if a < b { (a, b, normal, relative_velocity) }
+            else { (b, a, -normal, -relative_velocity) };
+
+        return ContactEvent {
+            phase: ContactPhase::Begin,
+            a,
+            b,
+            depth,
+            normal,
+            relative_velocity
+        };
+    }
+
+    fn pair(&self) -> (BallHandle, BallHandle)
+    {
+        return (self.a, self.b);
+    }
+}
+
+pub type ContactCallback<F = real> = Box<dyn FnMut(&ContactEvent<F>)>;
+
+// Works out which contacts began, persisted or ended between steps, and hands
+// the events to a callback or queues them up to be drained.
+pub struct ContactTracker<F: Float = real>
+{
+    previous: Vec<ContactEvent<F>>,
+    queue: Vec<ContactEvent<F>>,
+    callback: Option<ContactCallback<F>>
+}
+
+impl<F: Float> ContactTracker<F>
+{
+    pub fn new() -> ContactTracker<F>
+    {
+        return ContactTracker {
+            previous: Vec::new(),
+            queue: Vec::new(),
+            callback: None
+        };
+    }
+
+    pub fn set_callback(&mut self, callback: Option<ContactCallback<F>>)
+    {
+        self.callback = callback;
+    }
+
+    // Takes every contact found this step, in any order with at most one
+    // per pair, and emits events sorted by pair.
+    pub fn update(&mut self, mut current: Vec<ContactEvent<F>>)
+    {
+        current.sort_unstable_by_key(|c| c.pair());
+        let previous = mem::take(&mut self.previous);
+
+        let mut p = 0;
+        for c in current.iter_mut()
+        {
+            while p < previous.len() && previous[p].pair() < c.pair()
+            {
+                self.emit(ContactEvent { phase: ContactPhase::End, ..previous[p] });
+                p += 1;
+            }
+
+            c.phase = if p < previous.len() && previous[p].pair() == c.pair()
+            {
+                p += 1;
+                ContactPhase::Persist
+            }
+            else
+            {
+                ContactPhase::Begin
+            };
+            self.emit(*c);
+        }
+        for ended in &previous[p..]
+        {
+            self.emit(ContactEvent { phase: ContactPhase::End, ..*ended });
+        }
+
+        self.previous = current;
+    }
+
+    fn emit(&mut self, event: ContactEvent<F>)
+    {
+        match &mut self.callback
+        {
+            Some(f) => f(&event),
+            None => self.queue.push(event)
+        }
+    }
+
+    pub fn drain(&mut self) -> Drain<'_, ContactEvent<F>>
+    {
+        return self.queue.drain(..);
+    }
+}
+
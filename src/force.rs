@@ -0,0 +1,315 @@
+use cgmath::InnerSpace;
+use std::hash::Hasher;
+
+use crate::ball::*;
+use crate::hash::*;
+use crate::maths::*;
+use crate::mesh::*;
+
+const FIELD_SIDES: usize = 32;
+const FIELD_WIDTH: real = 1.5;
+const ATTRACT_COLOUR: Colour = colour(90, 170, 110);
+const REPEL_COLOUR: Colour = colour(190, 90, 90);
+const VORTEX_COLOUR: Colour = colour(90, 130, 200);
+const WIND_COLOUR: Colour = colour(150, 150, 160);
+
+// Something that pushes balls around. Every generator on the world adds its
+// acceleration to gravity for every awake ball before each integration.
+pub trait ForceGenerator<F: Float = real>: Send + Sync
+{
+    // Acceleration on the ball in units per second squared. dt is the length
+    // of the step, to turn the ball's velocity into units per second.
+    fn acceleration(&self, b: &Ball<F>, dt: F) -> Vec2<F>;
+
+    // Feeds the kind of generator and everything it depends on into the
+    // hasher, for the world's state hash
+    fn hash_state(&self, h: &mut StateHasher);
+
+    // Adds a picture of where the generator acts, if it has one
+    fn draw(&self, _mesh: &mut Mesh) {}
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Falloff
+{
+    // Full strength anywhere inside the radius
+    Constant,
+    // Full strength at the centre down to nothing at the radius
+    Linear,
+    // Roughly strength / distance², softened near the centre so it stays
+    // finite, and cut off at the radius
+    InverseSquare
+}
+
+impl Falloff
+{
+    // Fraction of full strength at dist from the centre
+    fn factor<F: Float>(self, dist: F, radius: F) -> F
+    {
+        if dist >= radius { return F::zero(); }
+
+        return match self
+        {
+            Falloff::Constant => F::one(),
+            Falloff::Linear => F::one() - (dist / radius),
+            Falloff::InverseSquare =>
+            {
+                let core = radius * F::of(0.1);
+                (core * core) / ((dist * dist) + (core * core))
+            }
+        };
+    }
+}
+
+// Pulls balls towards a point, or pushes them away with negative strength
+#[derive(Copy, Clone, Debug)]
+pub struct Attractor<F: Float = real>
+{
+    pub centre: Vec2<F>,
+    pub strength: F,
+    pub radius: F,
+    pub falloff: Falloff
+}
+
+impl<F: Float> Attractor<F>
+{
+    pub fn new(centre: Vec2<F>, strength: F, radius: F, falloff: Falloff) -> Attractor<F>
+    {
+        return Attractor {
+            centre,
+            strength,
+            radius,
+            falloff
+        };
+    }
+    pub fn repulsor(centre: Vec2<F>, strength: F, radius: F, falloff: Falloff) -> Attractor<F>
+    {
+        return Attractor::new(centre, -strength, radius, falloff);
+    }
+}
+
+impl<F: Float> ForceGenerator<F> for Attractor<F>
+{
+    fn acceleration(&self, b: &Ball<F>, _dt: F) -> Vec2<F>
+    {
+        let offset = self.centre - b.location;
+        let dist = offset.magnitude();
+        if dist == F::zero() { return vec2(F::zero(), F::zero()); }
+
+        return offset * (self.strength * self.falloff.factor(dist, self.radius) / dist);
+    }
+
+    fn hash_state(&self, h: &mut StateHasher)
+    {
+        h.write_u8(0);
+        h.vec2(self.centre);
+        h.real(self.strength);
+        h.real(self.radius);
+        h.write_u8(self.falloff as u8);
+    }
+
+    fn draw(&self, mesh: &mut Mesh)
+    {
+        let c = if self.strength >= F::zero() { ATTRACT_COLOUR } else { REPEL_COLOUR };
+        let centre = cast2(self.centre);
+        mesh.add_loop(&circle_points(centre, self.radius.as_f32(), FIELD_SIDES), FIELD_WIDTH, c_to_v(c));
+        mesh.add_circle(centre, FIELD_WIDTH * 3.0, c_to_v(c));
+    }
+}
+
+// Swirls balls around a point, counter-clockwise for positive strength
+#[derive(Copy, Clone, Debug)]
+pub struct Vortex<F: Float = real>
+{
+    pub centre: Vec2<F>,
+    pub strength: F,
+    pub radius: F,
+    pub falloff: Falloff
+}
+
+impl<F: Float> Vortex<F>
+{
+    pub fn new(centre: Vec2<F>, strength: F, radius: F, falloff: Falloff) -> Vortex<F>
+    {
+        return Vortex {
+            centre,
+            strength,
+            radius,
+            falloff
+        };
+    }
+}
+
+impl<F: Float> ForceGenerator<F> for Vortex<F>
+{
+    fn acceleration(&self, b: &Ball<F>, _dt: F) -> Vec2<F>
+    {
+        let offset = b.location - self.centre;
+        let dist = offset.magnitude();
+        if dist == F::zero() { return vec2(F::zero(), F::zero()); }
+
+        let tangent = vec2(-offset.y, offset.x) / dist;
+        return tangent * (self.strength * self.falloff.factor(dist, self.radius));
+    }
+
+    fn hash_state(&self, h: &mut StateHasher)
+    {
+        h.write_u8(1);
+        h.vec2(self.centre);
+        h.real(self.strength);
+        h.real(self.radius);
+        h.write_u8(self.falloff as u8);
+    }
+
+    fn draw(&self, mesh: &mut Mesh)
+    {
+        let c = c_to_v(VORTEX_COLOUR);
+        let (centre, radius) = (cast2(self.centre), self.radius.as_f32());
+        mesh.add_loop(&circle_points(centre, radius, FIELD_SIDES), FIELD_WIDTH, c);
+        mesh.add_loop(&circle_points(centre, radius * 0.5, FIELD_SIDES), FIELD_WIDTH, c);
+    }
+}
+
+// Constant acceleration on balls whose centre is inside a rectangle
+#[derive(Copy, Clone, Debug)]
+pub struct Wind<F: Float = real>
+{
+    pub min: Vec2<F>,
+    pub max: Vec2<F>,
+    pub acceleration: Vec2<F>
+}
+
+impl<F: Float> Wind<F>
+{
+    pub fn new(min: Vec2<F>, max: Vec2<F>, acceleration: Vec2<F>) -> Wind<F>
+    {
+        return Wind {
+            min,
+            max,
+            acceleration
+        };
+    }
+}
+
+impl<F: Float> ForceGenerator<F> for Wind<F>
+{
+    fn acceleration(&self, b: &Ball<F>, _dt: F) -> Vec2<F>
+    {
+        let l = b.location;
+        if l.x < self.min.x || l.x > self.max.x || l.y < self.min.y || l.y > self.max.y
+        {
+            return vec2(F::zero(), F::zero());
+        }
+        return self.acceleration;
+    }
+
+    fn hash_state(&self, h: &mut StateHasher)
+    {
+        h.write_u8(2);
+        h.vec2(self.min);
+        h.vec2(self.max);
+        h.vec2(self.acceleration);
+    }
+
+    fn draw(&self, mesh: &mut Mesh)
+    {
+        let c = c_to_v(WIND_COLOUR);
+        let (min, max): (Vec2, Vec2) = (cast2(self.min), cast2(self.max));
+        mesh.add_loop(&[min, vec2(max.x, min.y), max, vec2(min.x, max.y)], FIELD_WIDTH, c);
+
+        // Arrow through the middle pointing downwind
+        if self.acceleration == vec2(F::zero(), F::zero()) { return; }
+        let centre = (min + max) * 0.5;
+        let dir = cast2::<F, real>(self.acceleration).normalize() * ((max - min).magnitude() * 0.25);
+        let tip = centre + dir;
+        mesh.add_line(centre - dir, tip, FIELD_WIDTH, c);
+        mesh.add_line(tip, tip - rotate(dir * 0.3, 0.5), FIELD_WIDTH, c);
+        mesh.add_line(tip, tip - rotate(dir * 0.3, -0.5), FIELD_WIDTH, c);
+    }
+}
+
+// Air resistance and damping, applied straight to a ball's velocity as it
+// is integrated rather than as a force, so it can never reverse a ball
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Drag
+{
+    // Fraction of velocity lost per second at any speed
+    pub linear: real,
+    // Fraction lost per second for each unit per second of speed, so fast
+    // balls slow down much more than slow ones
+    pub quadratic: real,
+    // Fraction of velocity removed every DAMPING_STEP seconds, spread over
+    // however many steps or substeps that takes
+    pub damping: real
+}
+
+// Length of time damping is given for, a frame at 60 fps
+pub const DAMPING_STEP: real = 1.0 / 60.0;
+
+impl Drag
+{
+    pub const NONE: Drag = Drag::new(0.0, 0.0, 0.0);
+    pub const AIR: Drag = Drag::new(0.1, 0.0005, 0.0);
+
+    pub const fn new(linear: real, quadratic: real, damping: real) -> Drag
+    {
+        return Drag {
+            linear,
+            quadratic,
+            damping
+        };
+    }
+
+    // vel is the distance moved over a step of length dt
+    pub fn apply<F: Float>(&self, vel: Vec2<F>, dt: F) -> Vec2<F>
+    {
+        if *self == Drag::NONE { return vel; }
+
+        let speed = vel.magnitude() / dt;
+        let loss = ((F::of(self.linear.into()) + (F::of(self.quadratic.into()) * speed)) * dt).min(F::one());
+        let kept = (F::one() - F::of(self.damping.into())).powf(dt / F::of(DAMPING_STEP.into()));
+        return vel * ((F::one() - loss) * kept);
+    }
+}
+
+impl Default for Drag
+{
+    fn default() -> Self
+    {
+        return Drag::NONE;
+    }
+}
+
+// Slows every ball in proportion to its speed, as a generator so it can be
+// added alongside the others. Uses the same sums as Drag's linear term.
+#[derive(Copy, Clone, Debug)]
+pub struct LinearDrag
+{
+    // Fraction of velocity lost per second, roughly, for small values
+    pub coefficient: real
+}
+
+impl LinearDrag
+{
+    pub fn new(coefficient: real) -> LinearDrag
+    {
+        return LinearDrag {
+            coefficient
+        };
+    }
+}
+
+impl<F: Float> ForceGenerator<F> for LinearDrag
+{
+    fn acceleration(&self, b: &Ball<F>, dt: F) -> Vec2<F>
+    {
+        let vel = b.velocity();
+        return (Drag::new(self.coefficient, 0.0, 0.0).apply(vel, dt) - vel) / (dt * dt);
+    }
+
+    fn hash_state(&self, h: &mut StateHasher)
+    {
+        h.write_u8(3);
+        h.real(self.coefficient);
+    }
+}
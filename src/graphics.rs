@@ -39,14 +39,14 @@ impl DrawObject
             &util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
                 contents: bytemuck::cast_slice(vertex),
-                usage: BufferUsages::VERTEX,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
             }
         );
         let index_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
                 contents: bytemuck::cast_slice(index),
-                usage: wgpu::BufferUsages::INDEX,
+                usage: wgpu::BufferUsages::INDEX | BufferUsages::COPY_DST,
             }
         );
         
@@ -59,6 +59,31 @@ impl DrawObject
         };
     }
     
+    // Replaces what is drawn, reusing the buffers if it fits in them
+    pub fn write<V: Pod + Zeroable, I: IndexFormatType + Pod + Zeroable>(&mut self, device: &Device, queue: &Queue,
+        vertex: &[V], index: &[I])
+    {
+        if index.is_empty()
+        {
+            self.length = 0;
+            return;
+        }
+
+        let (vertex_bytes, index_bytes): (&[u8], &[u8]) = (bytemuck::cast_slice(vertex), bytemuck::cast_slice(index));
+        // Writes have to be whole words
+        let aligned = (vertex_bytes.len() as u64).is_multiple_of(COPY_BUFFER_ALIGNMENT) && (index_bytes.len() as u64).is_multiple_of(COPY_BUFFER_ALIGNMENT);
+        if !aligned || self.index_format != I::get_format() ||
+            self.vertex_buffer.size() < vertex_bytes.len() as u64 || self.index_buffer.size() < index_bytes.len() as u64
+        {
+            *self = DrawObject::new(device, vertex, index);
+            return;
+        }
+
+        queue.write_buffer(&self.vertex_buffer, 0, vertex_bytes);
+        queue.write_buffer(&self.index_buffer, 0, index_bytes);
+        self.length = index.len() as u32;
+    }
+
     pub fn draw(&self, render_pass: &mut RenderPass<'_>, count: u32)
     {
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
@@ -0,0 +1,365 @@
+use std::ops::Range;
+
+use crate::ball::*;
+use crate::maths::*;
+use crate::periodic::*;
+
+// Upper limit on cells per ball, so tiny radii in a big window don't allocate
+// millions of empty cells.
+const MAX_CELLS_PER_BALL: usize = 4;
+
+// Uniform grid broadphase. Balls are counting sorted into cells at least one
+// diameter of the largest ball wide, so any two touching balls are always in
+// the same or adjacent cells. Cells are stored column major so a range of
+// columns is contiguous. Along a wrapped axis the cells are stretched to fit
+// the bounds exactly, and the last row or column neighbours the first.
+pub struct Grid<F: Float = real>
+{
+    origin: Vec2<F>,
+    cell_size: Vec2<F>,
+    columns: usize,
+    rows: usize,
+    // Whether the last column or row neighbours the first
+    wrap_x: bool,
+    wrap_y: bool,
+    starts: Vec<u32>,
+    indices: Vec<u32>,
+    ball_cells: Vec<u32>
+}
+
+impl<F: Float> Grid<F>
+{
+    pub fn new() -> Grid<F>
+    {
+        return Grid {
+            origin: vec2(F::zero(), F::zero()),
+            cell_size: vec2(F::one(), F::one()),
+            columns: 1,
+            rows: 1,
+            wrap_x: false,
+            wrap_y: false,
+            starts: Vec::new(),
+            indices: Vec::new(),
+            ball_cells: Vec::new()
+        };
+    }
+
+    pub fn build(&mut self, balls: &[Ball<F>], bounds: Vec4<F>, periodic: Periodic)
+    {
+        let width = (bounds.y - bounds.x).max(F::one());
+        let height = (bounds.z - bounds.w).max(F::one());
+
+        let max_radius = balls.iter().fold(F::zero(), |m: F, b| m.max(b.radius));
+        let max_cells = (balls.len() * MAX_CELLS_PER_BALL).max(1);
+        let min_size = (width * height / F::of(max_cells as f64)).sqrt();
+
+        self.origin = vec2(bounds.x, bounds.w);
+        let size = (max_radius * F::of(2.0)).max(min_size);
+        (self.columns, self.cell_size.x) = fit_cells(width, size, periodic.x);
+        (self.rows, self.cell_size.y) = fit_cells(height, size, periodic.y);
+        self.wrap_x = periodic.x;
+        self.wrap_y = periodic.y;
+
+        let cell_count = self.columns * self.rows;
+        self.starts.clear();
+        self.starts.resize(cell_count + 1, 0);
+        self.ball_cells.clear();
+
+        for b in balls
+        {
+            let (x, y) = self.cell_of(b.location);
+            let c = self.index(x, y);
+            self.ball_cells.push(c as u32);
+            self.starts[c + 1] += 1;
+        }
+        for c in 0..cell_count
+        {
+            self.starts[c + 1] += self.starts[c];
+        }
+
+        // Scatter using the next free slot of each cell, then shift back.
+        self.indices.clear();
+        self.indices.resize(balls.len(), 0);
+        for (i, &c) in self.ball_cells.iter().enumerate()
+        {
+            let slot = &mut self.starts[c as usize];
+            self.indices[*slot as usize] = i as u32;
+            *slot += 1;
+        }
+        for c in (1..=cell_count).rev()
+        {
+            self.starts[c] = self.starts[c - 1];
+        }
+        self.starts[0] = 0;
+    }
+
+    pub fn columns(&self) -> usize
+    {
+        return self.columns;
+    }
+    pub fn rows(&self) -> usize
+    {
+        return self.rows;
+    }
+
+    pub fn cell_of(&self, p: Vec2<F>) -> (usize, usize)
+    {
+        let rel = p - self.origin;
+        let rel = vec2(rel.x / self.cell_size.x, rel.y / self.cell_size.y);
+        let x = (rel.x.max(F::zero()).as_f64() as usize).min(self.columns - 1);
+        let y = (rel.y.max(F::zero()).as_f64() as usize).min(self.rows - 1);
+        return (x, y);
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> &[u32]
+    {
+        let c = self.index(x, y);
+        return &self.indices[self.starts[c] as usize..self.starts[c + 1] as usize];
+    }
+
+    // Calls f for every pair of balls in neighbouring cells, visiting the
+    // cells in the given columns. Each cell only looks at itself and the cells
+    // above and to the right, so every pair is seen once. Pairs may reach one
+    // column past the end of the range, but never round from the last column
+    // to the first, which seam_pairs is for.
+    pub fn pairs<P: FnMut(usize, usize)>(&self, columns: Range<usize>, mut f: P)
+    {
+        for x in columns
+        {
+            for y in 0..self.rows
+            {
+                let cell = self.cell(x, y);
+                for (n, &i) in cell.iter().enumerate()
+                {
+                    for &j in &cell[(n + 1)..]
+                    {
+                        f(i as usize, j as usize);
+                    }
+                }
+
+                if let Some(up) = self.row_above(y)
+                {
+                    self.cross(cell, x, up, &mut f);
+                }
+                if x + 1 < self.columns
+                {
+                    self.right(cell, x + 1, y, &mut f);
+                }
+            }
+        }
+    }
+
+    // Calls f for every pair across the seam from the last column to the
+    // first, if x wraps. Kept out of pairs so a strip of columns never
+    // reaches round to balls another strip owns. With one or two columns
+    // the columns either side of the seam already met in pairs.
+    pub fn seam_pairs<P: FnMut(usize, usize)>(&self, mut f: P)
+    {
+        if !self.wrap_x || self.columns < 3 { return; }
+
+        for y in 0..self.rows
+        {
+            let cell = self.cell(self.columns - 1, y);
+            self.right(cell, 0, y, &mut f);
+        }
+    }
+
+    // Calls f for every ball whose cell is near the rectangle from min to max.
+    // The search is padded by a cell so balls poking in from outside are found.
+    pub fn region<R: FnMut(usize)>(&self, min: Vec2<F>, max: Vec2<F>, mut f: R)
+    {
+        let pad = self.cell_size;
+        let (x0, y0) = self.cell_of(min - pad);
+        let (x1, y1) = self.cell_of(max + pad);
+        for x in x0..=x1
+        {
+            for y in y0..=y1
+            {
+                self.cell(x, y).iter().for_each(|&i| f(i as usize));
+            }
+        }
+    }
+
+    // Walks the cells along a ray from origin in the unit direction dir, up to
+    // length, calling f with every cell it passes through.
+    pub fn ray<R: FnMut(usize, usize)>(&self, origin: Vec2<F>, dir: Vec2<F>, length: F, mut f: R)
+    {
+        let min = self.origin;
+        let max = self.origin + vec2(F::of(self.columns as f64) * self.cell_size.x, F::of(self.rows as f64) * self.cell_size.y);
+
+        // Clip the ray to the grid
+        let mut t0: F = F::zero();
+        let mut t1 = length;
+        for axis in 0..2
+        {
+            if dir[axis] == F::zero()
+            {
+                if origin[axis] < min[axis] || origin[axis] > max[axis] { return; }
+                continue;
+            }
+            let a = (min[axis] - origin[axis]) / dir[axis];
+            let b = (max[axis] - origin[axis]) / dir[axis];
+            t0 = t0.max(a.min(b));
+            t1 = t1.min(a.max(b));
+        }
+        if t0 > t1 { return; }
+
+        let (mut x, mut y) = self.cell_of(origin + (dir * t0));
+        let step_x: isize = if dir.x < F::zero() { -1 } else { 1 };
+        let step_y: isize = if dir.y < F::zero() { -1 } else { 1 };
+        let delta = vec2((self.cell_size.x / dir.x).abs(), (self.cell_size.y / dir.y).abs());
+        let next_x = self.origin.x + (F::of((x as isize + step_x.max(0)) as f64) * self.cell_size.x);
+        let next_y = self.origin.y + (F::of((y as isize + step_y.max(0)) as f64) * self.cell_size.y);
+        let mut t_x = if dir.x == F::zero() { F::max_value() } else { (next_x - origin.x) / dir.x };
+        let mut t_y = if dir.y == F::zero() { F::max_value() } else { (next_y - origin.y) / dir.y };
+
+        loop
+        {
+            f(x, y);
+
+            let t = if t_x < t_y
+            {
+                x = x.wrapping_add_signed(step_x);
+                t_x += delta.x;
+                t_x - delta.x
+            }
+            else
+            {
+                y = y.wrapping_add_signed(step_y);
+                t_y += delta.y;
+                t_y - delta.y
+            };
+
+            if t > t1 || x >= self.columns || y >= self.rows { return; }
+        }
+    }
+
+    // Pairs between a cell and the three cells of column x beside it
+    fn right<P: FnMut(usize, usize)>(&self, cell: &[u32], x: usize, y: usize, f: &mut P)
+    {
+        if let Some(down) = self.row_below(y)
+        {
+            self.cross(cell, x, down, f);
+        }
+        self.cross(cell, x, y, f);
+        if let Some(up) = self.row_above(y)
+        {
+            self.cross(cell, x, up, f);
+        }
+    }
+
+    // The rows either side of y, wrapping round if y does. With one or two
+    // rows the row across the seam is one already beside it, so is left out
+    // rather than giving the same pairs twice.
+    fn row_above(&self, y: usize) -> Option<usize>
+    {
+        if y + 1 < self.rows { return Some(y + 1); }
+        return if self.wrap_y && self.rows >= 3 { Some(0) } else { None };
+    }
+    fn row_below(&self, y: usize) -> Option<usize>
+    {
+        if y > 0 { return Some(y - 1); }
+        return if self.wrap_y && self.rows >= 3 { Some(self.rows - 1) } else { None };
+    }
+
+    fn cross<P: FnMut(usize, usize)>(&self, cell: &[u32], x: usize, y: usize, f: &mut P)
+    {
+        let other = self.cell(x, y);
+        for &i in cell
+        {
+            for &j in other
+            {
+                f(i as usize, j as usize);
+            }
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize
+    {
+        return (x * self.rows) + y;
+    }
+}
+
+// How many cells of at least size fit along length, and how big they are.
+// Wrapped axes stretch the cells to tile it exactly, so the seam falls on a
+// cell edge, others leave the last cell hanging over the end.
+fn fit_cells<F: Float>(length: F, size: F, wrap: bool) -> (usize, F)
+{
+    if wrap
+    {
+        let count = ((length / size).floor().as_f64() as usize).max(1);
+        return (count, length / F::of(count as f64));
+    }
+    return (((length / size).ceil().as_f64() as usize).max(1), size);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use cgmath::InnerSpace;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+
+    // Every pair the grid gives, each once, ordered low index first
+    fn grid_pairs(grid: &Grid) -> HashSet<(usize, usize)>
+    {
+        let mut found = HashSet::new();
+        let mut add = |i: usize, j: usize|
+        {
+            assert!(found.insert((i.min(j), i.max(j))), "pair {} {} seen twice", i, j);
+        };
+        grid.pairs(0..grid.columns(), &mut add);
+        grid.seam_pairs(&mut add);
+        return found;
+    }
+
+    // Checks the grid finds every pair touching by the shortest way round
+    fn check(balls: &[Ball], bounds: Vec4<real>, periodic: Periodic)
+    {
+        let mut grid = Grid::new();
+        grid.build(balls, bounds, periodic);
+        let found = grid_pairs(&grid);
+
+        for i in 0..balls.len()
+        {
+            for j in (i + 1)..balls.len()
+            {
+                let reach = balls[i].radius + balls[j].radius;
+                if periodic.offset(balls[i].location, balls[j].location, bounds).magnitude2() < reach * reach
+                {
+                    assert!(found.contains(&(i, j)), "touching pair {} {} missed with {}x{} cells", i, j, grid.columns(), grid.rows());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_match_brute_force()
+    {
+        let mut rng = StdRng::seed_from_u64(1);
+        let bounds = vec4(-400.0, 400.0, 300.0, -300.0);
+        let area = vec2(-400.0, -300.0)..vec2(400.0, 300.0);
+        let balls: Vec<Ball> = (0..2000).map(|_| Ball::random(&mut rng, &area, 1.0..8.0)).collect();
+        check(&balls, bounds, Periodic::NONE);
+        check(&balls, bounds, Periodic::new(true, true));
+    }
+
+    #[test]
+    fn wrapped_pairs_with_few_cells()
+    {
+        let mut rng = StdRng::seed_from_u64(4);
+        let bounds = vec4(-400.0, 400.0, 300.0, -300.0);
+        let area = vec2(-400.0, -300.0)..vec2(400.0, 300.0);
+        // Biggest radii giving one, two and three cells across
+        for max_radius in [300.0, 190.0, 130.0]
+        {
+            let balls: Vec<Ball> = (0..80).map(|_| Ball::random(&mut rng, &area, 10.0..max_radius)).collect();
+            for periodic in [Periodic::new(true, false), Periodic::new(false, true), Periodic::new(true, true)]
+            {
+                check(&balls, bounds, periodic);
+            }
+        }
+    }
+}
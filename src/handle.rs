@@ -0,0 +1,101 @@
+// Marks a slot with no ball in it
+const FREE: u32 = u32::MAX;
+
+// Stable reference to a ball. Stays valid while the ball exists, even as
+// other balls are removed, and never refers to a different ball afterwards.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct BallHandle
+{
+    index: u32,
+    generation: u32
+}
+
+#[derive(Copy, Clone)]
+struct Slot
+{
+    generation: u32,
+    dense: u32
+}
+
+// Maps handles to indices into a densely packed array that is kept packed by
+// swap removal. Slots are reused with a bumped generation, so old handles to
+// a reused slot no longer match.
+pub struct Handles
+{
+    slots: Vec<Slot>,
+    owners: Vec<u32>,
+    free: Vec<u32>
+}
+
+impl Handles
+{
+    pub fn new() -> Handles
+    {
+        return Handles {
+            slots: Vec::new(),
+            owners: Vec::new(),
+            free: Vec::new()
+        };
+    }
+
+    // Handle for a new item pushed onto the end of the dense array
+    pub fn insert(&mut self) -> BallHandle
+    {
+        let dense = self.owners.len() as u32;
+        let index = match self.free.pop()
+        {
+            Some(i) =>
+            {
+                self.slots[i as usize].dense = dense;
+                i
+            }
+            None =>
+            {
+                self.slots.push(Slot { generation: 0, dense });
+                (self.slots.len() - 1) as u32
+            }
+        };
+
+        self.owners.push(index);
+        return BallHandle {
+            index,
+            generation: self.slots[index as usize].generation
+        };
+    }
+
+    pub fn get(&self, h: BallHandle) -> Option<usize>
+    {
+        let slot = self.slots.get(h.index as usize)?;
+        if slot.generation != h.generation || slot.dense == FREE { return None; }
+
+        return Some(slot.dense as usize);
+    }
+
+    // Handle of the item at a dense index
+    pub fn handle(&self, dense: usize) -> BallHandle
+    {
+        let index = self.owners[dense];
+        return BallHandle {
+            index,
+            generation: self.slots[index as usize].generation
+        };
+    }
+
+    // Frees the handle and returns the dense index to swap remove, with
+    // the last item's handle moved to point at that index.
+    pub fn remove(&mut self, h: BallHandle) -> Option<usize>
+    {
+        let dense = self.get(h)?;
+
+        let last = *self.owners.last()?;
+        self.slots[last as usize].dense = dense as u32;
+        self.owners.swap_remove(dense);
+
+        let slot = &mut self.slots[h.index as usize];
+        slot.dense = FREE;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(h.index);
+
+        return Some(dense);
+    }
+}
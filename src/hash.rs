@@ -0,0 +1,71 @@
+use std::hash::Hasher;
+
+use crate::maths::*;
+
+const OFFSET: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+// FNV-1a, used instead of the std hasher as its output is fixed across
+// platforms and Rust versions, so state hashes from different runs compare.
+pub struct StateHasher
+{
+    hash: u64
+}
+
+impl StateHasher
+{
+    pub fn new() -> StateHasher
+    {
+        return StateHasher {
+            hash: OFFSET
+        };
+    }
+
+    // Hashes the exact bits, so values that print the same but differ in
+    // the last place still give different hashes
+    pub fn real<F: Float>(&mut self, x: F)
+    {
+        if F::BYTES == 4
+        {
+            self.write_u32(x.bits() as u32);
+            return;
+        }
+        self.write_u64(x.bits());
+    }
+    pub fn vec2<F: Float>(&mut self, v: Vec2<F>)
+    {
+        self.real(v.x);
+        self.real(v.y);
+    }
+}
+
+impl Hasher for StateHasher
+{
+    fn finish(&self) -> u64
+    {
+        return self.hash;
+    }
+
+    fn write(&mut self, bytes: &[u8])
+    {
+        for &b in bytes
+        {
+            self.hash ^= b as u64;
+            self.hash = self.hash.wrapping_mul(PRIME);
+        }
+    }
+
+    // Little endian whatever the platform, rather than native order
+    fn write_u32(&mut self, i: u32)
+    {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u64(&mut self, i: u64)
+    {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_usize(&mut self, i: usize)
+    {
+        self.write_u64(i as u64);
+    }
+}
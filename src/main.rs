@@ -1,9 +1,28 @@
 mod ball;
+mod constraint;
+mod container;
+mod diagnostics;
+mod events;
+mod force;
+mod grid;
+mod handle;
+mod hash;
+mod material;
+mod mesh;
+mod nbody;
+mod obstacle;
+mod parallel;
+mod periodic;
 mod physics;
+mod sleep;
+mod softbody;
+mod snapshot;
 mod program;
+mod replay;
 mod maths;
 mod graphics;
 mod state;
+mod timestep;
 #[macro_use]
 mod helpers;
 
@@ -0,0 +1,50 @@
+use crate::maths::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Material
+{
+    // Fraction of normal velocity kept when bouncing, 0 to 1
+    pub restitution: real,
+    // Fraction of tangential velocity removed while in contact, 0 to 1
+    pub friction: real,
+    pub density: real
+}
+
+impl Material
+{
+    pub const DEFAULT: Material = Material::new(0.0, 0.0, 1.0);
+    pub const RUBBER: Material = Material::new(0.9, 0.3, 0.5);
+    pub const SAND: Material = Material::new(0.0, 0.8, 3.0);
+    pub const STEEL: Material = Material::new(1.0, 0.0, 8.0);
+
+    pub const PRESETS: [(&'static str, Material); 4] = [
+        ("Default", Material::DEFAULT),
+        ("Rubber", Material::RUBBER),
+        ("Sand", Material::SAND),
+        ("Steel", Material::STEEL)
+    ];
+
+    pub const fn new(restitution: real, friction: real, density: real) -> Material
+    {
+        return Material {
+            restitution,
+            friction,
+            density
+        };
+    }
+
+    // Restitution and friction of a contact between two materials. The
+    // bouncier of the two wins, friction is the geometric mean.
+    pub fn combine(a: &Material, b: &Material) -> (real, real)
+    {
+        return (a.restitution.max(b.restitution), (a.friction * b.friction).sqrt());
+    }
+}
+
+impl Default for Material
+{
+    fn default() -> Self
+    {
+        return Material::DEFAULT;
+    }
+}
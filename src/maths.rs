@@ -1,12 +1,86 @@
-use cgmath::{InnerSpace, Vector2, Vector3, Vector4};
+use std::f64::consts::TAU;
+use std::fmt::Display;
+use std::str::FromStr;
+use cgmath::num_traits::float::TotalOrder;
+use cgmath::{BaseFloat, InnerSpace, Vector2, Vector3, Vector4};
 
+// What the renderer works in, and the physics unless told otherwise
 #[allow(non_camel_case_types)]
 pub type real = f32;
-pub type Vec2 = Vector2<real>;
+pub type Vec2<F = real> = Vector2<F>;
 pub type Vec3 = Vector3<real>;
-pub type Vec4 = Vector4<real>;
+pub type Vec4<F = real> = Vector4<F>;
 pub type Colour = Vector3<u8>;
 
+// A float type the physics can run in. f32 matches what gets drawn, f64 is
+// for long runs where rounding error would otherwise add up.
+pub trait Float: BaseFloat + TotalOrder + Default + Display + FromStr + Send + Sync + 'static
+{
+    // Bytes each value takes, which binary snapshots store them in
+    const BYTES: usize;
+
+    // For constants, which can't be written as literals of an unknown type
+    fn of(x: f64) -> Self;
+    fn as_f32(self) -> f32;
+    fn as_f64(self) -> f64;
+    // The exact bits, widened to 64
+    fn bits(self) -> u64;
+}
+
+impl Float for f32
+{
+    const BYTES: usize = 4;
+
+    fn of(x: f64) -> f32
+    {
+        return x as f32;
+    }
+    fn as_f32(self) -> f32
+    {
+        return self;
+    }
+    fn as_f64(self) -> f64
+    {
+        return self as f64;
+    }
+    fn bits(self) -> u64
+    {
+        return self.to_bits() as u64;
+    }
+}
+
+impl Float for f64
+{
+    const BYTES: usize = 8;
+
+    fn of(x: f64) -> f64
+    {
+        return x;
+    }
+    fn as_f32(self) -> f32
+    {
+        return self as f32;
+    }
+    fn as_f64(self) -> f64
+    {
+        return self;
+    }
+    fn bits(self) -> u64
+    {
+        return self.to_bits();
+    }
+}
+
+// Converts a vector between float types
+pub fn cast2<A: Float, B: Float>(v: Vec2<A>) -> Vec2<B>
+{
+    return vec2(B::of(v.x.as_f64()), B::of(v.y.as_f64()));
+}
+pub fn cast4<A: Float, B: Float>(v: Vec4<A>) -> Vec4<B>
+{
+    return vec4(B::of(v.x.as_f64()), B::of(v.y.as_f64()), B::of(v.z.as_f64()), B::of(v.w.as_f64()));
+}
+
 pub const fn size_bounds(width: real, height: real) -> Vec4
 {
     let hw = width * 0.5;
@@ -14,7 +88,7 @@ pub const fn size_bounds(width: real, height: real) -> Vec4
     return vec4(-hw, hw, hh, -hh);
 }
 
-pub const fn vec4(x: real, y: real, z: real, w: real) -> Vec4
+pub const fn vec4<F>(x: F, y: F, z: F, w: F) -> Vec4<F>
 {   
     return Vec4::new(x, y, z, w);
 }
@@ -22,10 +96,33 @@ pub const fn vec3(x: real, y: real, z: real) -> Vec3
 {
     return Vec3::new(x, y, z);
 }
-pub const fn vec2(x: real, y: real) -> Vec2
+pub const fn vec2<F>(x: F, y: F) -> Vec2<F>
 {
     return Vec2::new(x, y);
 }
+pub fn rotate<F: Float>(v: Vec2<F>, radians: F) -> Vec2<F>
+{
+    let (sin, cos) = radians.sin_cos();
+    return vec2((v.x * cos) - (v.y * sin), (v.x * sin) + (v.y * cos));
+}
+pub fn circle_points<F: Float>(centre: Vec2<F>, radius: F, sides: usize) -> Vec<Vec2<F>>
+{
+    return (0..sides)
+        .map(|i| rotate(vec2(radius, F::zero()), F::of(TAU * i as f64 / sides as f64)) + centre)
+        .collect();
+}
+// Positive when the points go round counter-clockwise
+pub fn signed_area<F: Float>(points: &[Vec2<F>]) -> F
+{
+    let mut area = F::zero();
+    for i in 0..points.len()
+    {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        area += (a.x * b.y) - (b.x * a.y);
+    }
+    return area * F::of(0.5);
+}
 pub const fn colour(r: u8, g: u8, b: u8) -> Colour
 {
     return Colour::new(r, g, b);
@@ -0,0 +1,159 @@
+use cgmath::InnerSpace;
+use wgpu::*;
+
+use crate::maths::*;
+
+// Segments a full circle is split into
+const CIRCLE_SIDES: usize = 24;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ShapeVertex
+{
+    position: Vec2,
+    colour: Vec3
+}
+unsafe impl bytemuck::Pod for ShapeVertex {}
+unsafe impl bytemuck::Zeroable for ShapeVertex {}
+impl ShapeVertex
+{
+    const ATTRIBS: [VertexAttribute; 2] =
+        vertex_attr_array![0 => Float32x2, 1 => Float32x3];
+
+    pub const fn desc() -> VertexBufferLayout<'static>
+    {
+        return VertexBufferLayout {
+            array_stride: std::mem::size_of::<ShapeVertex>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS
+        }
+    }
+}
+
+// Flat coloured triangles, built up on the CPU and drawn in one call.
+// Everything is wound counter-clockwise to survive back face culling.
+pub struct Mesh
+{
+    pub vertices: Vec<ShapeVertex>,
+    pub indices: Vec<u32>
+}
+
+impl Mesh
+{
+    pub fn new() -> Mesh
+    {
+        return Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new()
+        };
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        return self.indices.is_empty();
+    }
+
+    fn vertex(&mut self, position: Vec2, colour: Vec3) -> u32
+    {
+        self.vertices.push(ShapeVertex { position, colour });
+        return (self.vertices.len() - 1) as u32;
+    }
+
+    pub fn add_line(&mut self, a: Vec2, b: Vec2, width: real, colour: Vec3)
+    {
+        let d = b - a;
+        let len = d.magnitude();
+        if len == 0.0 { return; }
+
+        let n = vec2(-d.y, d.x) * (width * 0.5 / len);
+        let i = self.vertex(a - n, colour);
+        self.vertex(b - n, colour);
+        self.vertex(b + n, colour);
+        self.vertex(a + n, colour);
+        self.indices.extend_from_slice(&[i, i + 1, i + 2, i + 2, i + 3, i]);
+    }
+
+    // Outline of a closed loop of points
+    pub fn add_loop(&mut self, points: &[Vec2], width: real, colour: Vec3)
+    {
+        for i in 0..points.len()
+        {
+            self.add_line(points[i], points[(i + 1) % points.len()], width, colour);
+        }
+    }
+    
+    // Fan from the first point, so it must be convex or at least visible in
+    // full from the first point, in counter-clockwise order.
+    pub fn add_polygon(&mut self, points: &[Vec2], colour: Vec3)
+    {
+        if points.len() < 3 { return; }
+
+        let start = self.vertex(points[0], colour);
+        for p in &points[1..]
+        {
+            self.vertex(*p, colour);
+        }
+        for i in 1..(points.len() as u32 - 1)
+        {
+            self.indices.extend_from_slice(&[start, start + i, start + i + 1]);
+        }
+    }
+
+    // Any loop of points that doesn't cross itself, concave or not, in
+    // either order. Ear clipped, so it costs the square of the points.
+    pub fn add_outline(&mut self, points: &[Vec2], colour: Vec3)
+    {
+        if points.len() < 3 { return; }
+
+        let start = self.vertex(points[0], colour);
+        for p in &points[1..]
+        {
+            self.vertex(*p, colour);
+        }
+        let mut ring: Vec<usize> = (0..points.len()).collect();
+        if signed_area(points) < 0.0
+        {
+            ring.reverse();
+        }
+
+        while ring.len() > 3
+        {
+            // Squashed inside out there may be no ear, so one is cut anyway
+            // to finish, which is wrong but only where the body is
+            let n = ring.len();
+            let k = (0..n).find(|&k| is_ear(points, &ring, k)).unwrap_or(0);
+            let (a, b, c) = (ring[(k + n - 1) % n], ring[k], ring[(k + 1) % n]);
+            self.indices.extend_from_slice(&[start + a as u32, start + b as u32, start + c as u32]);
+            ring.remove(k);
+        }
+        self.indices.extend(ring.iter().map(|&i| start + i as u32));
+    }
+
+    pub fn add_circle(&mut self, centre: Vec2, radius: real, colour: Vec3)
+    {
+        let points = circle_points(centre, radius, CIRCLE_SIDES);
+        self.add_polygon(&points, colour);
+    }
+}
+
+fn cross(a: Vec2, b: Vec2) -> real
+{
+    return (a.x * b.y) - (a.y * b.x);
+}
+
+// Whether the corner at ring[k] of a counter-clockwise ring can be cut off,
+// being convex with no other corner inside it
+fn is_ear(points: &[Vec2], ring: &[usize], k: usize) -> bool
+{
+    let n = ring.len();
+    let (a, b, c) = (points[ring[(k + n - 1) % n]], points[ring[k]], points[ring[(k + 1) % n]]);
+    if cross(b - a, c - b) <= 0.0 { return false; }
+
+    return ring.iter().enumerate()
+        .filter(|&(j, _)| j != k && j != (k + n - 1) % n && j != (k + 1) % n)
+        .all(|(_, &i)|
+        {
+            let p = points[i];
+            cross(b - a, p - a) < 0.0 || cross(c - b, p - b) < 0.0 || cross(a - c, p - c) < 0.0
+        });
+}
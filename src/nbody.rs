@@ -0,0 +1,333 @@
+use cgmath::InnerSpace;
+
+use crate::ball::*;
+use crate::maths::*;
+
+// Bodies closer together than this many halvings of the root end up sharing
+// a leaf rather than splitting forever
+const MAX_DEPTH: u32 = 32;
+const NO_BODY: u32 = u32::MAX;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Method
+{
+    // Every pair, O(n²). Slow but exact, for checking the tree against.
+    Exact,
+    // Groups of bodies whose size seen from a ball is below theta are
+    // treated as a single body at their centre of mass. 0 is exact, larger
+    // is faster and rougher, 0.5 is the usual choice.
+    BarnesHut { theta: real }
+}
+
+// Every ball pulls every other ball towards it in proportion to its mass
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Gravitation
+{
+    // Strength of the pull, in units³ per mass per second squared
+    pub constant: real,
+    // Distance added to every separation, so close passes don't fling
+    // balls out at huge speeds
+    pub softening: real,
+    pub method: Method
+}
+
+impl Gravitation
+{
+    pub const fn new(constant: real, softening: real, method: Method) -> Gravitation
+    {
+        return Gravitation {
+            constant,
+            softening,
+            method
+        };
+    }
+
+    // Acceleration from a body of mass at offset from the ball
+    fn pull<F: Float>(&self, offset: Vec2<F>, mass: F) -> Vec2<F>
+    {
+        let softening = F::of(self.softening.into());
+        let d2 = offset.magnitude2() + (softening * softening);
+        if d2 == F::zero() { return vec2(F::zero(), F::zero()); }
+
+        return offset * (F::of(self.constant.into()) * mass / (d2 * d2.sqrt()));
+    }
+
+    // Potential energy per unit mass from a body of mass at offset
+    fn potential<F: Float>(&self, offset: Vec2<F>, mass: F) -> F
+    {
+        let softening = F::of(self.softening.into());
+        let d2 = offset.magnitude2() + (softening * softening);
+        if d2 == F::zero() { return F::zero(); }
+
+        return -F::of(self.constant.into()) * mass / d2.sqrt();
+    }
+
+    // Acceleration on ball i from every other ball, summed pair by pair
+    pub fn exact<F: Float>(&self, balls: &[Ball<F>], i: usize) -> Vec2<F>
+    {
+        let p = balls[i].location;
+        let mut acc = vec2(F::zero(), F::zero());
+        for (j, b) in balls.iter().enumerate()
+        {
+            if j == i { continue; }
+            acc += self.pull(b.location - p, b.mass());
+        }
+        return acc;
+    }
+
+    // Potential energy per unit mass at ball i, summed pair by pair
+    pub fn exact_potential<F: Float>(&self, balls: &[Ball<F>], i: usize) -> F
+    {
+        let p = balls[i].location;
+        let mut phi = F::zero();
+        for (j, b) in balls.iter().enumerate()
+        {
+            if j == i { continue; }
+            phi += self.potential(b.location - p, b.mass());
+        }
+        return phi;
+    }
+}
+
+impl Default for Gravitation
+{
+    fn default() -> Self
+    {
+        return Gravitation::new(100.0, 4.0, Method::BarnesHut { theta: 0.5 });
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Node<F: Float>
+{
+    centre: Vec2<F>,
+    half: F,
+    mass: F,
+    // Mass weighted sum of locations while building, centre of mass after
+    com: Vec2<F>,
+    // Index of the first of four children, or 0 for a leaf
+    children: u32,
+    // The first body put in a leaf, or NO_BODY if it is empty
+    body: u32,
+    count: u32
+}
+
+impl<F: Float> Node<F>
+{
+    fn new(centre: Vec2<F>, half: F) -> Node<F>
+    {
+        return Node {
+            centre,
+            half,
+            mass: F::zero(),
+            com: vec2(F::zero(), F::zero()),
+            children: 0,
+            body: NO_BODY,
+            count: 0
+        };
+    }
+
+    // Which child a point belongs in, in the same order they are stored
+    fn quadrant(&self, p: Vec2<F>) -> u32
+    {
+        return (p.x >= self.centre.x) as u32 + (((p.y >= self.centre.y) as u32) << 1);
+    }
+
+    fn contains(&self, p: Vec2<F>) -> bool
+    {
+        let min = self.centre - vec2(self.half, self.half);
+        let max = self.centre + vec2(self.half, self.half);
+        return p.x >= min.x && p.y >= min.y && p.x < max.x && p.y < max.y;
+    }
+}
+
+// Barnes-Hut quadtree over the balls' masses. Nodes are stored flat, with
+// the four children of a node next to each other.
+pub struct QuadTree<F: Float = real>
+{
+    nodes: Vec<Node<F>>
+}
+
+impl<F: Float> QuadTree<F>
+{
+    pub fn new() -> QuadTree<F>
+    {
+        return QuadTree {
+            nodes: Vec::new()
+        };
+    }
+
+    pub fn build(&mut self, balls: &[Ball<F>])
+    {
+        self.nodes.clear();
+
+        let mut min = vec2(F::max_value(), F::max_value());
+        let mut max = vec2(F::min_value(), F::min_value());
+        for b in balls
+        {
+            min = vec2(min.x.min(b.location.x), min.y.min(b.location.y));
+            max = vec2(max.x.max(b.location.x), max.y.max(b.location.y));
+        }
+        if balls.is_empty() { return; }
+
+        // Square, and a little bigger so the furthest balls are strictly inside
+        let half = ((max.x - min.x).max(max.y - min.y) * F::of(0.5)).max(F::one()) * F::of(1.01);
+        self.nodes.push(Node::new((min + max) * F::of(0.5), half));
+
+        for i in 0..balls.len()
+        {
+            self.insert(balls, i as u32);
+        }
+        for n in &mut self.nodes
+        {
+            if n.mass > F::zero()
+            {
+                n.com /= n.mass;
+            }
+        }
+    }
+
+    fn insert(&mut self, balls: &[Ball<F>], body: u32)
+    {
+        let p = balls[body as usize].location;
+        let mass = balls[body as usize].mass();
+        let mut node = 0;
+        let mut depth = 0;
+        loop
+        {
+            let n = &mut self.nodes[node];
+            if n.children == 0
+            {
+                if n.body == NO_BODY || depth >= MAX_DEPTH
+                {
+                    // Empty, or too deep to split so the bodies share it
+                    if n.body == NO_BODY { n.body = body; }
+                    n.mass += mass;
+                    n.com += p * mass;
+                    n.count += 1;
+                    return;
+                }
+
+                // Split the leaf, moving its one body down a level
+                let (centre, half, old) = (n.centre, n.half * F::of(0.5), n.body);
+                let first = self.nodes.len() as u32;
+                let n = &mut self.nodes[node];
+                n.children = first;
+                n.body = NO_BODY;
+                for q in 0..4
+                {
+                    let x = if q & 1 == 0 { -half } else { half };
+                    let y = if q & 2 == 0 { -half } else { half };
+                    self.nodes.push(Node::new(centre + vec2(x, y), half));
+                }
+
+                let n = self.nodes[node];
+                let b = &balls[old as usize];
+                let child = &mut self.nodes[(n.children + n.quadrant(b.location)) as usize];
+                child.body = old;
+                child.mass = b.mass();
+                child.com = b.location * child.mass;
+                child.count = 1;
+            }
+
+            let n = &mut self.nodes[node];
+            n.mass += mass;
+            n.com += p * mass;
+            n.count += 1;
+            node = (n.children + n.quadrant(p)) as usize;
+            depth += 1;
+        }
+    }
+
+    // Acceleration on ball i from every other ball
+    pub fn acceleration(&self, balls: &[Ball<F>], i: usize, g: &Gravitation, theta: real) -> Vec2<F>
+    {
+        let mut acc = vec2(F::zero(), F::zero());
+        self.walk(balls, i, theta, |offset, mass| acc += g.pull(offset, mass));
+        return acc;
+    }
+
+    // Potential energy per unit mass at ball i from every other ball
+    pub fn potential(&self, balls: &[Ball<F>], i: usize, g: &Gravitation, theta: real) -> F
+    {
+        let mut phi = F::zero();
+        self.walk(balls, i, theta, |offset, mass| phi += g.potential(offset, mass));
+        return phi;
+    }
+
+    // Calls f with the offset from ball i and the mass of each body or group
+    // of bodies that stands in for everything else
+    fn walk<W: FnMut(Vec2<F>, F)>(&self, balls: &[Ball<F>], i: usize, theta: real, mut f: W)
+    {
+        if self.nodes.is_empty() { return; }
+
+        let p = balls[i].location;
+        let theta = F::of(theta.into());
+        let theta2 = theta * theta;
+        let mut stack = vec![0u32];
+        while let Some(node) = stack.pop()
+        {
+            let n = &self.nodes[node as usize];
+            if n.mass == F::zero() { continue; }
+
+            // Nodes holding the ball are always opened, so it never pulls itself
+            let offset = n.com - p;
+            let size = n.half * F::of(2.0);
+            if n.children != 0 && (n.contains(p) || size * size >= theta2 * offset.magnitude2())
+            {
+                stack.extend(n.children..(n.children + 4));
+                continue;
+            }
+
+            if n.children == 0 && n.body == i as u32 && n.count == 1 { continue; }
+            if n.children == 0 && n.count > 1 && n.contains(p)
+            {
+                // A shared leaf holding this ball, so take it back out
+                let own = balls[i].mass();
+                let rest = n.mass - own;
+                if rest > F::zero()
+                {
+                    f((((n.com * n.mass) - (p * own)) / rest) - p, rest);
+                }
+                continue;
+            }
+            f(offset, n.mass);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // Root mean square error of the tree's accelerations against the exact
+    // ones, as a fraction of the exact ones' root mean square
+    fn tree_error(theta: real) -> real
+    {
+        let mut rng = StdRng::seed_from_u64(2);
+        let area = vec2(-400.0, -300.0)..vec2(400.0, 300.0);
+        let balls: Vec<Ball> = (0..500).map(|_| Ball::random(&mut rng, &area, 1.0..6.0)).collect();
+        let g = Gravitation::new(1000.0, 5.0, Method::Exact);
+        let mut tree = QuadTree::new();
+        tree.build(&balls);
+
+        let (mut error, mut size) = (0.0, 0.0);
+        for i in 0..balls.len()
+        {
+            let exact = g.exact(&balls, i);
+            error += (tree.acceleration(&balls, i, &g, theta) - exact).magnitude2();
+            size += exact.magnitude2();
+        }
+        return (error / size).sqrt();
+    }
+
+    #[test]
+    fn barnes_hut_is_close_to_exact()
+    {
+        assert!(tree_error(0.0) < 1e-4);
+        assert!(tree_error(0.3) < 0.01);
+    }
+}
@@ -0,0 +1,204 @@
+use cgmath::InnerSpace;
+use std::hash::Hasher;
+
+use crate::hash::*;
+use crate::maths::*;
+
+#[derive(Clone, Debug)]
+pub enum Shape<F: Float = real>
+{
+    Segment { a: Vec2<F>, b: Vec2<F> },
+    Circle { centre: Vec2<F>, radius: F },
+    // Convex, with points in counter-clockwise order
+    Polygon(Vec<Vec2<F>>)
+}
+
+// A static shape the balls collide with but never move
+#[derive(Clone, Debug)]
+pub struct Obstacle<F: Float = real>
+{
+    shape: Shape<F>,
+    bounds: Vec4<F>
+}
+
+impl<F: Float> Obstacle<F>
+{
+    pub fn new(shape: Shape<F>) -> Obstacle<F>
+    {
+        let shape = match shape
+        {
+            Shape::Polygon(mut points) =>
+            {
+                if signed_area(&points) < F::zero()
+                {
+                    points.reverse();
+                }
+                Shape::Polygon(points)
+            }
+            s => s
+        };
+
+        let bounds = match &shape
+        {
+            Shape::Segment { a, b } => points_bounds(&[*a, *b]),
+            Shape::Circle { centre, radius } =>
+                vec4(centre.x - *radius, centre.x + *radius, centre.y + *radius, centre.y - *radius),
+            Shape::Polygon(points) => points_bounds(points)
+        };
+
+        return Obstacle {
+            shape,
+            bounds
+        };
+    }
+
+    pub fn segment(a: Vec2<F>, b: Vec2<F>) -> Obstacle<F>
+    {
+        return Obstacle::new(Shape::Segment { a, b });
+    }
+    pub fn circle(centre: Vec2<F>, radius: F) -> Obstacle<F>
+    {
+        return Obstacle::new(Shape::Circle { centre, radius });
+    }
+    pub fn polygon(points: Vec<Vec2<F>>) -> Obstacle<F>
+    {
+        return Obstacle::new(Shape::Polygon(points));
+    }
+
+    pub fn shape(&self) -> &Shape<F>
+    {
+        return &self.shape;
+    }
+
+    // Returns the normal pointing out of the obstacle and how far a circle
+    // at centre has sunk into it, if they overlap.
+    pub fn contact(&self, centre: Vec2<F>, radius: F) -> Option<(Vec2<F>, F)>
+    {
+        let b = self.bounds;
+        if centre.x + radius < b.x || centre.x - radius > b.y ||
+            centre.y - radius > b.z || centre.y + radius < b.w
+        {
+            return None;
+        }
+
+        return match &self.shape
+        {
+            Shape::Segment { a, b } => point_contact(centre, radius, closest_on_segment(centre, *a, *b), perp(*b - *a)),
+            Shape::Circle { centre: c, radius: r } => point_contact(centre, radius + *r, *c, vec2(F::zero(), F::one())),
+            Shape::Polygon(points) => polygon_contact(centre, radius, points)
+        };
+    }
+
+    pub fn hash_state(&self, h: &mut StateHasher)
+    {
+        match &self.shape
+        {
+            Shape::Segment { a, b } =>
+            {
+                h.write_u8(0);
+                h.vec2(*a);
+                h.vec2(*b);
+            }
+            Shape::Circle { centre, radius } =>
+            {
+                h.write_u8(1);
+                h.vec2(*centre);
+                h.real(*radius);
+            }
+            Shape::Polygon(points) =>
+            {
+                h.write_u8(2);
+                h.write_usize(points.len());
+                for p in points
+                {
+                    h.vec2(*p);
+                }
+            }
+        }
+    }
+}
+
+fn perp<F: Float>(v: Vec2<F>) -> Vec2<F>
+{
+    return vec2(-v.y, v.x);
+}
+
+fn points_bounds<F: Float>(points: &[Vec2<F>]) -> Vec4<F>
+{
+    let mut bounds = vec4(F::max_value(), F::min_value(), F::min_value(), F::max_value());
+    for p in points
+    {
+        bounds.x = bounds.x.min(p.x);
+        bounds.y = bounds.y.max(p.x);
+        bounds.z = bounds.z.max(p.y);
+        bounds.w = bounds.w.min(p.y);
+    }
+    return bounds;
+}
+
+pub fn closest_on_segment<F: Float>(p: Vec2<F>, a: Vec2<F>, b: Vec2<F>) -> Vec2<F>
+{
+    let ab = b - a;
+    let len = ab.magnitude2();
+    if len == F::zero() { return a; }
+
+    let t = ((p - a).dot(ab) / len).clamp(F::zero(), F::one());
+    return a + (ab * t);
+}
+
+// Contact between a circle and a single point on a surface. fallback is used
+// as the normal when the centre lies exactly on the point, or straight up if
+// it has no direction either, as for a segment of zero length.
+fn point_contact<F: Float>(centre: Vec2<F>, radius: F, point: Vec2<F>, fallback: Vec2<F>) -> Option<(Vec2<F>, F)>
+{
+    let d = centre - point;
+    let dist = d.magnitude2();
+    if dist >= radius * radius { return None; }
+
+    if dist == F::zero()
+    {
+        let zero = vec2(F::zero(), F::zero());
+        let normal = if fallback == zero { vec2(F::zero(), F::one()) } else { fallback.normalize() };
+        return Some((normal, radius));
+    }
+
+    let dist = dist.sqrt();
+    return Some((d / dist, radius - dist));
+}
+
+fn polygon_contact<F: Float>(centre: Vec2<F>, radius: F, points: &[Vec2<F>]) -> Option<(Vec2<F>, F)>
+{
+    // Deepest edge, for when the centre is inside
+    let mut max_sep = F::min_value();
+    let mut max_normal = vec2(F::zero(), F::one());
+    // Closest point on the outline, for when it is outside
+    let mut closest = centre;
+    let mut closest_dist = F::max_value();
+
+    for i in 0..points.len()
+    {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        let normal = -perp(b - a).normalize();
+        let sep = (centre - a).dot(normal);
+        if sep > max_sep
+        {
+            max_sep = sep;
+            max_normal = normal;
+        }
+
+        let p = closest_on_segment(centre, a, b);
+        let dist = (centre - p).magnitude2();
+        if dist < closest_dist
+        {
+            closest_dist = dist;
+            closest = p;
+        }
+    }
+
+    if max_sep <= F::zero()
+    {
+        return Some((max_normal, radius - max_sep));
+    }
+    return point_contact(centre, radius, closest, max_normal);
+}
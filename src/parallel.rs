@@ -0,0 +1,70 @@
+use std::thread;
+
+// Fewest items worth handing a thread of their own. Below this spawning the
+// thread costs more than the work it takes off the others.
+pub const MIN_PER_THREAD: usize = 256;
+
+// Raw view of a slice that can be shared between worker threads. Callers
+// must guarantee no two threads touch the same element at once, which the
+// solver does by colouring the grid.
+pub struct Shared<T>
+{
+    ptr: *mut T,
+    len: usize
+}
+
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T>
+{
+    pub fn new(items: &mut [T]) -> Shared<T>
+    {
+        return Shared {
+            ptr: items.as_mut_ptr(),
+            len: items.len()
+        };
+    }
+
+    // SAFETY: i must be in range and not be accessed by any other thread
+    // until the returned reference is dropped.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get(&self, i: usize) -> &mut T
+    {
+        debug_assert!(i < self.len);
+        return &mut *self.ptr.add(i);
+    }
+}
+
+// How many threads len items are worth splitting across, at most threads
+pub fn worker_count(len: usize, threads: usize) -> usize
+{
+    return threads.min(len / MIN_PER_THREAD).max(1);
+}
+
+// Runs f over every item, splitting the slice into one chunk per thread.
+pub fn for_each_mut<T: Send, F: Fn(&mut T) + Sync>(items: &mut [T], threads: usize, f: F)
+{
+    for_each_index_mut(items, threads, |_, t| f(t));
+}
+
+// Like for_each_mut, but also passes each item's index in the slice
+pub fn for_each_index_mut<T: Send, F: Fn(usize, &mut T) + Sync>(items: &mut [T], threads: usize, f: F)
+{
+    let threads = worker_count(items.len(), threads);
+    if threads <= 1
+    {
+        items.iter_mut().enumerate().for_each(|(i, t)| f(i, t));
+        return;
+    }
+
+    let chunk = items.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|s|
+    {
+        for (n, part) in items.chunks_mut(chunk).enumerate()
+        {
+            s.spawn(move || part.iter_mut().enumerate().for_each(|(i, t)| f((n * chunk) + i, t)));
+        }
+    });
+}
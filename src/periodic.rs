@@ -0,0 +1,113 @@
+use crate::maths::*;
+
+// Which axes of the bounds wrap around, so a ball leaving one edge comes
+// back in on the opposite one instead of hitting a wall
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Periodic
+{
+    pub x: bool,
+    pub y: bool
+}
+
+impl Periodic
+{
+    pub const NONE: Periodic = Periodic::new(false, false);
+
+    pub const fn new(x: bool, y: bool) -> Periodic
+    {
+        return Periodic {
+            x,
+            y
+        };
+    }
+
+    // The shortest way from a to b, which may go across the seams of the
+    // wrapped axes rather than straight there
+    pub fn offset<F: Float>(&self, a: Vec2<F>, b: Vec2<F>, bounds: Vec4<F>) -> Vec2<F>
+    {
+        return (b - a) + self.image(a, b, bounds);
+    }
+
+    // How far to move b, a whole number of widths or heights, to put it as
+    // close to a as it can be
+    pub fn image<F: Float>(&self, a: Vec2<F>, b: Vec2<F>, bounds: Vec4<F>) -> Vec2<F>
+    {
+        let d = b - a;
+        let mut shift = vec2(F::zero(), F::zero());
+        if self.x
+        {
+            shift.x = image_shift(d.x, bounds.y - bounds.x);
+        }
+        if self.y
+        {
+            shift.y = image_shift(d.y, bounds.z - bounds.w);
+        }
+        return shift;
+    }
+
+    // How far to move p to bring it back inside the bounds along the
+    // wrapped axes, a whole number of widths or heights
+    pub fn wrap<F: Float>(&self, p: Vec2<F>, bounds: Vec4<F>) -> Vec2<F>
+    {
+        let mut shift = vec2(F::zero(), F::zero());
+        if self.x
+        {
+            shift.x = wrap_shift(p.x, bounds.x, bounds.y - bounds.x);
+        }
+        if self.y
+        {
+            shift.y = wrap_shift(p.y, bounds.w, bounds.z - bounds.w);
+        }
+        return shift;
+    }
+
+    // Offsets to the copies of the bounds around them along the wrapped
+    // axes, starting with no offset at all
+    pub fn tiles<F: Float>(&self, bounds: Vec4<F>) -> Vec<Vec2<F>>
+    {
+        let (w, h) = (bounds.y - bounds.x, bounds.z - bounds.w);
+        let xs = if self.x { vec![F::zero(), w, -w] } else { vec![F::zero()] };
+        let ys = if self.y { vec![F::zero(), h, -h] } else { vec![F::zero()] };
+        return ys.iter().flat_map(|&y| xs.iter().map(move |&x| vec2(x, y))).collect();
+    }
+
+    // Offsets of the copies of a circle that poke in across the seams, for
+    // drawing a ball on both sides of an edge at once
+    pub fn ghosts<F: Float>(&self, centre: Vec2<F>, radius: F, bounds: Vec4<F>) -> Vec<Vec2<F>>
+    {
+        let side = |wrapped: bool, p: F, min: F, max: F|
+        {
+            if !wrapped { return F::zero(); }
+            if p - radius < min { return max - min; }
+            if p + radius > max { return min - max; }
+            return F::zero();
+        };
+        let dx = side(self.x, centre.x, bounds.x, bounds.y);
+        let dy = side(self.y, centre.y, bounds.w, bounds.z);
+
+        let mut ghosts = Vec::new();
+        if dx != F::zero() { ghosts.push(vec2(dx, F::zero())); }
+        if dy != F::zero() { ghosts.push(vec2(F::zero(), dy)); }
+        if dx != F::zero() && dy != F::zero() { ghosts.push(vec2(dx, dy)); }
+        return ghosts;
+    }
+}
+
+// The whole number of periods to add to d to bring it within half a period
+// of zero
+fn image_shift<F: Float>(d: F, period: F) -> F
+{
+    let half = period * F::of(0.5);
+    if d > half || d < -half
+    {
+        return -period * (d / period).round();
+    }
+    return F::zero();
+}
+
+fn wrap_shift<F: Float>(p: F, min: F, period: F) -> F
+{
+    if p >= min && p < min + period { return F::zero(); }
+
+    return -period * ((p - min) / period).floor();
+}
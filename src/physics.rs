@@ -1,27 +1,148 @@
 use cgmath::InnerSpace;
 
 use crate::ball::*;
+use crate::constraint::*;
+use crate::container::*;
+use crate::diagnostics::*;
+use crate::events::*;
+use crate::force::*;
+use crate::grid::*;
+use crate::handle::*;
+use crate::hash::*;
+use crate::material::*;
 use crate::maths::*;
+use crate::nbody::*;
+use crate::obstacle::*;
+use crate::parallel::*;
+use crate::periodic::*;
+use crate::sleep::*;
+use crate::softbody::*;
+use std::hash::{Hash, Hasher};
+use std::thread;
 use std::vec::Vec;
 use std::slice::Iter;
 
-pub struct Physics
+// More passes over the links per step makes long chains less stretchy
+const LINK_ITERATIONS: usize = 4;
+// Columns are split into at most this many strips whatever the thread count,
+// so the order pairs are solved in, and the result, doesn't depend on it
+const STRIPS: usize = 32;
+// Halvings of a substep spent finding when a swept ball first touches a wall
+const SWEEP_BISECTIONS: usize = 8;
+// Fewer balls than this can't hold a shape worth matching
+const MIN_BODY_SIZE: usize = 3;
+
+pub struct Physics<F: Float = real>
+{
+    balls: Vec<Ball<F>>,
+    handles: Handles,
+    links: Vec<Link<F>>,
+    bodies: Vec<SoftBody<F>>,
+    obstacles: Vec<Obstacle<F>>,
+    container: Container<F>,
+    bounds: Vec4<F>,
+    periodic: Periodic,
+    gravity: Vec2<F>,
+    forces: Vec<Box<dyn ForceGenerator<F>>>,
+    drag: Drag,
+    gravitation: Option<Gravitation>,
+    tree: QuadTree<F>,
+    collisions: Collisions,
+    grid: Grid<F>,
+    // Whether balls have moved since the grid was built
+    grid_stale: bool,
+    threads: usize,
+    impulses: bool,
+    ccd: bool,
+    contacts: Option<ContactTracker<F>>,
+    // Contacts from every substep so far this step, for the events and sleep
+    step_touches: Vec<Touch<F>>,
+    // Whether to measure diagnostics, and what the last step measured
+    diagnose: bool,
+    diagnostics: Option<Diagnostics<F>>,
+    sleep: Option<SleepSettings>,
+    // Whether every ball should be woken before the next step, so removing
+    // many balls doesn't wake them all each time
+    wake_pending: bool,
+    steps: u64
+}
+
+// What happens when two balls overlap
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Collisions
+{
+    // Pushed apart
+    Resolve,
+    // Combined into one ball with the mass and momentum of both, once at
+    // the end of each step rather than every substep, so balls are only
+    // ever removed, and dense indices shuffled, between steps
+    Merge,
+    // Left to pass through each other
+    Ignore
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RayHit<F: Float = real>
+{
+    pub handle: BallHandle,
+    pub distance: F,
+    pub point: Vec2<F>,
+    pub normal: Vec2<F>
+}
+
+fn pair_mut<F: Float>(balls: &mut [Ball<F>], i: usize, j: usize) -> (&mut Ball<F>, &mut Ball<F>)
+{
+    if i < j
+    {
+        let (v1, v2) = balls.split_at_mut(j);
+        return (&mut v1[i], &mut v2[0]);
+    }
+    
+    let (v1, v2) = balls.split_at_mut(i);
+    return (&mut v2[0], &mut v1[j]);
+}
+
+// A contact found by the solver, between balls at dense indices i and j
+struct Touch<F: Float>
 {
-    balls: Vec<Ball>,
-    bounds: Vec4
+    i: u32,
+    j: u32,
+    depth: F,
+    normal: Vec2<F>,
+    velocity: Vec2<F>
 }
 
-fn resolve_collisions(a: &mut Ball, b: &mut Ball)
+impl<F: Float> Touch<F>
+{
+    fn between(self, i: usize, j: usize) -> Touch<F>
+    {
+        return Touch {
+            i: i as u32,
+            j: j as u32,
+            ..self
+        };
+    }
+
+    fn pair(&self) -> (u32, u32)
+    {
+        return (self.i.min(self.j), self.i.max(self.j));
+    }
+}
+
+// Pushes a and b apart if they overlap, returning the contact from a's side.
+// apart is where a is from b, which across a seam isn't a.location - b.location.
+fn resolve_collisions<F: Float>(a: &mut Ball<F>, b: &mut Ball<F>, apart: Vec2<F>, impulses: bool) -> Option<Touch<F>>
 {
     let sum_radius = a.radius + b.radius;
-    let mut axis: Vec2 = a.location - b.location;
+    let mut axis: Vec2<F> = apart;
     let mut dist = axis.magnitude2();
     
-    if dist >= (sum_radius * sum_radius) { return; }
+    if dist >= (sum_radius * sum_radius) { return None; }
     
-    if dist == 0.0
+    if dist == F::zero()
     {
-        axis = (a.velocity() - b.velocity()).normalize();
+        let rel = a.velocity() - b.velocity();
+        axis = if rel == vec2(F::zero(), F::zero()) { vec2(F::one(), F::zero()) } else { rel.normalize() };
     }
     else
     {
@@ -29,88 +150,1087 @@ fn resolve_collisions(a: &mut Ball, b: &mut Ball)
         axis /= dist;
     }
     let diff = dist - sum_radius;
-    let scale = diff * 0.5;
+    let scale = diff * F::of(0.5);
     let offset = axis * scale;
     
-    let inv = 1.0 / sum_radius;
-    let mass_ratio_a = a.radius * inv;
-    let mass_ratio_b = b.radius * inv;
+    // Each ball moves by its share of the total inverse mass, so the
+    // heavier ball moves less and fixed balls don't move at all
+    let inv_a = a.inv_mass();
+    let inv_b = b.inv_mass();
+    let inv_sum = inv_a + inv_b;
+    let touch = Touch {
+        i: 0,
+        j: 0,
+        depth: sum_radius - dist,
+        normal: axis,
+        velocity: a.velocity() - b.velocity()
+    };
+    // Resting sleepers stay put, but still count as touching
+    if inv_sum == F::zero() || (a.is_asleep() && b.is_asleep()) { return Some(touch); }
     
+    let mass_ratio_a = inv_a / inv_sum;
+    let mass_ratio_b = inv_b / inv_sum;
+    
+    let vel_a = a.velocity();
+    let vel_b = b.velocity();
     a.location -= offset * mass_ratio_a;
     b.location += offset * mass_ratio_b;
+    
+    let (restitution, friction) = Material::combine(&a.material, &b.material);
+    if impulses
+    {
+        apply_impulse(a, b, axis, vel_a, vel_b);
+    }
+    else
+    {
+        // Pushing apart doesn't always stop the balls closing, so they are
+        // sent apart at least at restitution's share of the speed they closed at
+        let vn = (vel_a - vel_b).dot(axis);
+        let parting = (a.velocity() - b.velocity()).dot(axis);
+        let wanted = -vn * F::of(restitution.into());
+        if vn < F::zero() && parting < wanted
+        {
+            let kick = axis * (wanted - parting);
+            a.set_velocity(a.velocity() + (kick * mass_ratio_a));
+            b.set_velocity(b.velocity() - (kick * mass_ratio_b));
+        }
+    }
+    
+    if friction > 0.0
+    {
+        let rel = a.velocity() - b.velocity();
+        let slip = (rel - (axis * rel.dot(axis))) * F::of(friction.into());
+        a.set_velocity(a.velocity() - (slip * mass_ratio_a));
+        b.set_velocity(b.velocity() + (slip * mass_ratio_b));
+    }
+    
+    return Some(touch);
 }
 
-fn clip_to_bounds(b: &mut Ball, bounds: Vec4)
+// Keeps the velocities from before the position correction, so pushing the
+// balls apart doesn't add energy, then exchanges momentum along the axis.
+fn apply_impulse<F: Float>(a: &mut Ball<F>, b: &mut Ball<F>, axis: Vec2<F>, mut vel_a: Vec2<F>, mut vel_b: Vec2<F>)
 {
+    let vn = (vel_a - vel_b).dot(axis);
+    if vn < F::zero()
+    {
+        let (restitution, _) = Material::combine(&a.material, &b.material);
+        let inv_a = a.inv_mass();
+        let inv_b = b.inv_mass();
+        let j = -(F::one() + F::of(restitution.into())) * vn / (inv_a + inv_b);
+        vel_a += axis * (j * inv_a);
+        vel_b -= axis * (j * inv_b);
+    }
+    
+    a.set_velocity(vel_a);
+    b.set_velocity(vel_b);
+}
+
+// Reflects the part of vel going into a surface with the given normal,
+// scaled by the ball's restitution, and applies its friction along the surface.
+fn bounce<F: Float>(b: &mut Ball<F>, normal: Vec2<F>, vel: Vec2<F>)
+{
+    let vn = vel.dot(normal);
+    if vn >= F::zero() { return; }
+    
+    let m = b.material;
+    let tangent = vel - (normal * vn);
+    b.set_velocity((tangent * (F::one() - F::of(m.friction.into()))) - (normal * (vn * F::of(m.restitution.into()))));
+}
+
+// Wrapped axes bring the ball back in on the other side, the rest are walls
+fn clip_to_bounds<F: Float>(b: &mut Ball<F>, bounds: Vec4<F>, periodic: Periodic)
+{
+    if b.fixed { return; }
+    
+    let shift = periodic.wrap(b.location, bounds);
+    if shift != vec2(F::zero(), F::zero())
+    {
+        b.translate(shift);
+    }
+    
     let r = b.radius;
     let l = b.location;
+    let vel = b.velocity();
+    let mut normal = vec2(F::zero(), F::zero());
     
-    if l.x - r < bounds.x
+    if !periodic.x && l.x - r < bounds.x
     {
         b.location.x = bounds.x + r;
+        normal.x = F::one();
     }
-    if l.x + r > bounds.y
+    if !periodic.x && l.x + r > bounds.y
     {
         b.location.x = bounds.y - r;
+        normal.x = -F::one();
     }
-    if l.y + r > bounds.z
+    if !periodic.y && l.y + r > bounds.z
     {
         b.location.y = bounds.z - r;
+        normal.y = -F::one();
     }
-    if l.y - r < bounds.w
+    if !periodic.y && l.y - r < bounds.w
     {
         b.location.y = bounds.w + r;
+        normal.y = F::one();
+    }
+    
+    if normal != vec2(F::zero(), F::zero())
+    {
+        bounce(b, normal.normalize(), vel);
     }
 }
 
-impl Physics {
-    pub fn apply_phsyics(&mut self, dt: real)
+fn clip_to_container<F: Float>(b: &mut Ball<F>, container: &Container<F>, bounds: Vec4<F>, periodic: Periodic)
+{
+    if b.fixed { return; }
+    
+    if let Container::Bounds = container
+    {
+        clip_to_bounds(b, bounds, periodic);
+        return;
+    }
+    
+    if let Some((normal, depth)) = container.contact(b.location, b.radius)
+    {
+        let vel = b.velocity();
+        b.location += normal * depth;
+        bounce(b, normal, vel);
+    }
+}
+
+fn clip_to_obstacles<F: Float>(b: &mut Ball<F>, obstacles: &[Obstacle<F>])
+{
+    if b.fixed { return; }
+    
+    for o in obstacles
+    {
+        if let Some((normal, depth)) = o.contact(b.location, b.radius)
+        {
+            let vel = b.velocity();
+            b.location += normal * depth;
+            bounce(b, normal, vel);
+        }
+    }
+}
+
+// The ball a and b make when they merge, keeping the look and material of a.
+// A fixed ball stays where it is and swallows the other.
+fn merge<F: Float>(a: &Ball<F>, b: &Ball<F>) -> Ball<F>
+{
+    let (mass_a, mass_b) = (a.mass(), b.mass());
+    let mass = mass_a + mass_b;
+    let mut m = *a;
+    m.radius = ((a.radius * a.radius) + (b.radius * b.radius)).sqrt();
+    // Keeping the area only keeps the mass if the density is the same
+    if a.mass.is_some() || b.mass.is_some() || a.material.density != b.material.density
+    {
+        m.mass = Some(mass);
+    }
+    
+    if a.fixed || b.fixed
+    {
+        m.fixed = true;
+        m.location = if a.fixed { a.location } else { b.location };
+        m.set_velocity(vec2(F::zero(), F::zero()));
+        return m;
+    }
+    
+    m.location = ((a.location * mass_a) + (b.location * mass_b)) / mass;
+    m.set_velocity(((a.velocity() * mass_a) + (b.velocity() * mass_b)) / mass);
+    return m;
+}
+
+// Distance along a ray with unit direction dir to where it enters a circle
+fn ray_circle<F: Float>(origin: Vec2<F>, dir: Vec2<F>, centre: Vec2<F>, radius: F) -> Option<F>
+{
+    let m = origin - centre;
+    let b = m.dot(dir);
+    let c = m.magnitude2() - (radius * radius);
+    if c > F::zero() && b > F::zero() { return None; }
+    
+    let disc = (b * b) - c;
+    if disc < F::zero() { return None; }
+    
+    return Some((-b - disc.sqrt()).max(F::zero()));
+}
+
+// When a ball moving from start first touches something with the given
+// contact test, as a fraction of motion, along with the contact. Steps no
+// further than the radius at a time, so it can't skip over thin segments,
+// then bisects. Anything already touching at the start is left to clipping.
+fn sweep_contact<F: Float, C: Fn(Vec2<F>) -> Option<(Vec2<F>, F)>>(start: Vec2<F>, motion: Vec2<F>, radius: F, contact: C) -> Option<(F, Vec2<F>, F)>
+{
+    if contact(start).is_some() { return None; }
+    
+    let steps = (motion.magnitude() / radius).ceil().max(F::one()).as_f64() as usize;
+    let mut lo = F::zero();
+    for k in 1..=steps
+    {
+        let mut hi = F::of(k as f64) / F::of(steps as f64);
+        if contact(start + (motion * hi)).is_none()
+        {
+            lo = hi;
+            continue;
+        }
+        
+        for _ in 0..SWEEP_BISECTIONS
+        {
+            let mid = (lo + hi) * F::of(0.5);
+            if contact(start + (motion * mid)).is_some() { hi = mid; } else { lo = mid; }
+        }
+        let (normal, depth) = contact(start + (motion * hi))?;
+        return Some((hi, normal, depth));
+    }
+    return None;
+}
+
+// When a ball moving from start first reaches a wall of the bounds. Wrapped
+// axes have no walls.
+fn sweep_bounds<F: Float>(start: Vec2<F>, motion: Vec2<F>, radius: F, bounds: Vec4<F>, periodic: Periodic) -> Option<(F, Vec2<F>, F)>
+{
+    let walls = [
+        (periodic.x, start.x - radius - bounds.x, -motion.x, vec2(F::one(), F::zero())),
+        (periodic.x, bounds.y - start.x - radius, motion.x, vec2(-F::one(), F::zero())),
+        (periodic.y, bounds.z - start.y - radius, motion.y, vec2(F::zero(), -F::one())),
+        (periodic.y, start.y - radius - bounds.w, -motion.y, vec2(F::zero(), F::one()))
+    ];
+    // gap is how far inside the wall the ball starts, towards how fast it closes
+    return walls.iter()
+        .filter(|(wrapped, gap, towards, _)| !*wrapped && *gap >= F::zero() && *towards > *gap)
+        .map(|(_, gap, towards, normal)| (*gap / *towards, *normal, F::zero()))
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+}
+
+impl<F: Float> Physics<F> {
+    pub fn apply_phsyics(&mut self, dt: F)
+    {
+        if self.wake_pending
+        {
+            self.wake_all();
+            self.wake_pending = false;
+        }
+        
+        let bounds = self.bounds;
+        let periodic = self.wrapping();
+        let obstacles = &self.obstacles;
+        let container = &self.container;
+        for_each_mut(&mut self.balls, self.threads, |b|
+        {
+            if b.is_asleep() { return; }
+            clip_to_container(b, container, bounds, periodic);
+            clip_to_obstacles(b, obstacles);
+        });
+        
+        self.grid.build(&self.balls, self.bounds, self.wrapping());
+        let touches = match self.collisions
+        {
+            Collisions::Resolve => self.solve_collisions(self.contacts.is_some() || self.sleep.is_some() || self.diagnose),
+            // Merging waits for the end of the step
+            Collisions::Merge | Collisions::Ignore => Vec::new()
+        };
+        self.solve_links();
+        self.solve_bodies();
+        // Built once for both the potential energy and the pulls, as nothing
+        // moves the balls between the two
+        if let Some(Gravitation { method: Method::BarnesHut { .. }, .. }) = self.gravitation
+        {
+            self.tree.build(&self.balls);
+        }
+        // Measured once the contacts are solved but before gravity and forces
+        // are added, so balls at rest read as still
+        if self.diagnose
+        {
+            let deepest = touches.iter().fold(F::zero(), |d: F, t| d.max(t.depth));
+            self.diagnostics = Some(self.measure(dt, touches.len(), deepest));
+        }
+        
+        if self.contacts.is_some() || self.sleep.is_some()
+        {
+            // In units per second, as substeps are put together at the end
+            self.step_touches.extend(touches.into_iter().map(|t| Touch { velocity: t.velocity / dt, ..t }));
+        }
+        
+        let pulls = self.gravitation_pulls();
+        let gravity = self.gravity;
+        let forces = &self.forces;
+        let drag = &self.drag;
+        for_each_index_mut(&mut self.balls, self.threads, |i, b|
+        {
+            if b.fixed || b.is_asleep() { return; }
+            
+            let mut acceleration = forces.iter().fold(gravity, |a, f| a + f.acceleration(b, dt));
+            if let Some(p) = pulls.get(i)
+            {
+                acceleration += *p;
+            }
+            b.verlet(dt, acceleration, drag);
+        });
+        if self.ccd
+        {
+            self.sweep_fast_balls();
+        }
+        self.grid_stale = true;
+    }
+    
+    // Stops balls that moved further than their radius this substep where
+    // they first hit something on the way, and bounces them off it there,
+    // rather than letting them pass through or be pushed out the far side.
+    // Other balls are taken to be where they ended up, and are only swept
+    // against when collisions are being resolved.
+    fn sweep_fast_balls(&mut self)
+    {
+        let fast: Vec<usize> = self.balls.iter().enumerate()
+            .filter(|(_, b)| !b.fixed && !b.is_asleep() && b.velocity().magnitude2() > b.radius * b.radius)
+            .map(|(i, _)| i)
+            .collect();
+        if fast.is_empty() { return; }
+        
+        let (bounds, periodic) = (self.bounds, self.wrapping());
+        // With no copies of the path to search, balls are never in the way
+        let tiles = if self.collisions == Collisions::Resolve { periodic.tiles(bounds) } else { Vec::new() };
+        if !tiles.is_empty()
+        {
+            self.grid.build(&self.balls, bounds, periodic);
+        }
+        for i in fast
+        {
+            let b = &self.balls[i];
+            let (start, motion, radius) = (b.old_pos(), b.velocity(), b.radius);
+            
+            let mut wall = match self.container
+            {
+                Container::Bounds => sweep_bounds(start, motion, radius, bounds, periodic),
+                ref c => sweep_contact(start, motion, radius, |p| c.contact(p, radius))
+            };
+            for o in &self.obstacles
+            {
+                let hit = sweep_contact(start, motion, radius, |p| o.contact(p, radius));
+                if hit.is_some_and(|h| wall.is_none_or(|w| h.0 < w.0)) { wall = hit; }
+            }
+            
+            let length = motion.magnitude();
+            let dir = motion / length;
+            let end = b.location;
+            let min = vec2(start.x.min(end.x), start.y.min(end.y));
+            let max = vec2(start.x.max(end.x), start.y.max(end.y));
+            let mid = start + (motion * F::of(0.5));
+            // Each ball is swept against at its copy nearest the path, which
+            // across a seam is found by looking in the copies of the path
+            let mut ball: Option<(F, usize, Vec2<F>)> = None;
+            for &s in &tiles
+            {
+                self.grid.region(min + s, max + s, |j|
+                {
+                    if j == i { return; }
+                    let o = &self.balls[j];
+                    let centre = o.location + periodic.image(mid, o.location, bounds);
+                    // Starting out touching is left to the solver
+                    let Some(d) = ray_circle(start, dir, centre, radius + o.radius) else { return; };
+                    if d <= F::zero() || d > length { return; }
+                    
+                    let t = d / length;
+                    if ball.is_none_or(|(bt, bj, _)| t < bt || (t == bt && j < bj)) { ball = Some((t, j, centre)); }
+                });
+            }
+            
+            match (wall, ball)
+            {
+                (Some((t, normal, depth)), b) if b.is_none_or(|(bt, _, _)| t <= bt) =>
+                {
+                    let b = &mut self.balls[i];
+                    b.location = start + (motion * t) + (normal * depth);
+                    b.set_velocity(motion);
+                    bounce(b, normal, motion);
+                }
+                (_, Some((t, j, centre))) =>
+                {
+                    let (a, o) = pair_mut(&mut self.balls, i, j);
+                    a.location = start + (motion * t);
+                    a.set_velocity(motion);
+                    o.wake();
+                    let axis = (a.location - centre).normalize();
+                    apply_impulse(a, o, axis, motion, o.velocity());
+                }
+                _ => {}
+            }
+        }
+    }
+    
+    // Height along wrapped axes has no bottom, so gravity's potential energy
+    // is left out along them rather than jumping every time a ball wraps
+    fn measure(&self, dt: F, contacts: usize, max_penetration: F) -> Diagnostics<F>
     {
-        for b in self.balls.iter_mut()
+        let periodic = self.wrapping();
+        let gravity = vec2(
+            if periodic.x { F::zero() } else { self.gravity.x },
+            if periodic.y { F::zero() } else { self.gravity.y });
+        let mut d = Diagnostics::new(contacts, max_penetration);
+        for b in &self.balls
         {
-            clip_to_bounds(b, self.bounds);
+            if b.fixed { continue; }
+            
+            let m = b.mass();
+            let vel = b.velocity() / dt;
+            d.kinetic += F::of(0.5) * m * vel.magnitude2();
+            d.momentum += vel * m;
+            d.potential -= m * gravity.dot(b.location);
         }
         
-        let l = self.balls.len();
-        for i in 0..l
+        // Each pair is seen from both sides, so counts half each time
+        let Some(g) = self.gravitation else { return d; };
+        let balls = &self.balls;
+        let phi: Vec<F> = match g.method
         {
-            let ni = i + 1;
-            let (v1, v2) = self.balls.split_at_mut(ni); 
-            let b1 = &mut v1[i];
-            for j in 0..(l - ni)
+            Method::Exact => (0..balls.len()).map(|i| g.exact_potential(balls, i)).collect(),
+            Method::BarnesHut { theta } => (0..balls.len()).map(|i| self.tree.potential(balls, i, &g, theta)).collect()
+        };
+        d.potential += balls.iter().zip(&phi).fold(F::zero(), |sum, (b, p)| sum + (F::of(0.5) * b.mass() * *p));
+        return d;
+    }
+    
+    fn solve_links(&mut self)
+    {
+        let periodic = self.wrapping();
+        for _ in 0..LINK_ITERATIONS
+        {
+            for l in &self.links
             {
-                let b2 = &mut v2[j];
-                resolve_collisions(b1, b2);
+                let (Some(i), Some(j)) = (self.handles.get(l.a), self.handles.get(l.b)) else { continue; };
+                let (a, b) = pair_mut(&mut self.balls, i, j);
+                // Solved between a and whichever copy of b is closest
+                let shift = periodic.image(a.location, b.location, self.bounds);
+                if shift == vec2(F::zero(), F::zero())
+                {
+                    l.solve(a, b, LINK_ITERATIONS);
+                    continue;
+                }
+                b.translate(shift);
+                l.solve(a, b, LINK_ITERATIONS);
+                b.translate(-shift);
             }
         }
+    }
+    
+    fn solve_bodies(&mut self)
+    {
+        let periodic = self.wrapping();
+        for body in &self.bodies
+        {
+            let indices: Vec<usize> = body.members.iter().filter_map(|&h| self.handles.get(h)).collect();
+            if indices.iter().all(|&i| self.balls[i].is_asleep()) { continue; }
+            
+            // Members are gathered around the first, so a body straddling a
+            // seam is matched as one piece
+            let first = self.balls[indices[0]].location;
+            let shifts: Vec<Vec2<F>> = indices.iter().map(|&i| periodic.image(first, self.balls[i].location, self.bounds)).collect();
+            for (&i, &d) in indices.iter().zip(&shifts)
+            {
+                if d != vec2(F::zero(), F::zero()) { self.balls[i].translate(d); }
+            }
+            body.solve(&mut self.balls, &indices);
+            for (&i, &d) in indices.iter().zip(&shifts)
+            {
+                if d != vec2(F::zero(), F::zero()) { self.balls[i].translate(-d); }
+            }
+        }
+    }
+    
+    // Every overlapping pair becomes one ball, the heavier (or fixed) one
+    // taking in the other. Balls merged into are checked again against the
+    // rest of their pairs with their new size. The balls taken in are all
+    // removed together at the end.
+    fn merge_overlaps(&mut self)
+    {
+        let periodic = self.wrapping();
+        self.grid.build(&self.balls, self.bounds, periodic);
+        let mut pairs = Vec::new();
+        self.grid.pairs(0..self.grid.columns(), |i, j| pairs.push((i, j)));
+        self.grid.seam_pairs(|i, j| pairs.push((i, j)));
         
-        for b in self.balls.iter_mut()
+        let mut gone = vec![false; self.balls.len()];
+        for (i, j) in pairs
         {
-            b.verlet(dt);
+            if gone[i] || gone[j] { continue; }
+            
+            let (a, b) = (&self.balls[i], &self.balls[j]);
+            let reach = a.radius + b.radius;
+            if periodic.offset(b.location, a.location, self.bounds).magnitude2() >= reach * reach { continue; }
+            
+            let keep_a = a.fixed || (!b.fixed && a.mass() >= b.mass());
+            let (keep, lose) = if keep_a { (i, j) } else { (j, i) };
+            // Merged with the copy of the lost ball beside the kept one
+            let mut other = self.balls[lose];
+            let shift = periodic.image(self.balls[keep].location, other.location, self.bounds);
+            if shift != vec2(F::zero(), F::zero())
+            {
+                other.translate(shift);
+            }
+            self.balls[keep] = merge(&self.balls[keep], &other);
+            gone[lose] = true;
+        }
+        
+        let lost: Vec<BallHandle> = gone.iter().enumerate().filter(|(_, g)| **g).map(|(i, _)| self.handles.handle(i)).collect();
+        for h in lost
+        {
+            self.remove(h);
         }
     }
     
-    pub fn apply_phsyics_sub(&mut self, dt: real, sub: u8)
+    // Acceleration on each ball from every other, if gravitation is on. The
+    // tree has to have been built first.
+    fn gravitation_pulls(&self) -> Vec<Vec2<F>>
     {
-        let dt = dt / sub as real;
+        let Some(g) = self.gravitation else { return Vec::new(); };
         
+        let mut pulls = vec![vec2(F::zero(), F::zero()); self.balls.len()];
+        let balls = &self.balls;
+        match g.method
+        {
+            Method::Exact => for_each_index_mut(&mut pulls, self.threads, |i, p|
+            {
+                if balls[i].fixed || balls[i].is_asleep() { return; }
+                *p = g.exact(balls, i);
+            }),
+            Method::BarnesHut { theta } =>
+            {
+                let tree = &self.tree;
+                for_each_index_mut(&mut pulls, self.threads, |i, p|
+                {
+                    if balls[i].fixed || balls[i].is_asleep() { return; }
+                    *p = tree.acceleration(balls, i, &g, theta);
+                });
+            }
+        }
+        return pulls;
+    }
+    
+    // Returns the contacts found if record is set
+    fn solve_collisions(&mut self, record: bool) -> Vec<Touch<F>>
+    {
+        let columns = self.grid.columns();
+        let impulses = self.impulses;
+        let (bounds, periodic) = (self.bounds, self.wrapping());
+        let mut touches = Vec::new();
+        
+        // Split the columns into strips and solve even strips, then odd strips.
+        // A strip only writes to its own columns plus the first column of the
+        // next strip, so strips of the same colour never share a ball.
+        let width = columns.div_ceil(STRIPS);
+        let strips = columns.div_ceil(width);
+        let threads = worker_count(self.balls.len(), self.threads);
+        let shared = Shared::new(&mut self.balls);
+        let grid = &self.grid;
+        let shared = &shared;
+        let solve = move |run: &[usize]|
+        {
+            let mut found = Vec::new();
+            for &strip in run
+            {
+                let range = (strip * width)..((strip + 1) * width).min(columns);
+                grid.pairs(range, |i, j|
+                {
+                    // SAFETY: see the colouring above, i and j are distinct
+                    // balls owned by this strip for the whole pass.
+                    let (a, b) = unsafe { (shared.get(i), shared.get(j)) };
+                    let touch = resolve_collisions(a, b, periodic.offset(b.location, a.location, bounds), impulses);
+                    if record { found.extend(touch.map(|t| t.between(i, j))); }
+                });
+            }
+            found
+        };
+        
+        for colour in 0..2
+        {
+            let own: Vec<usize> = (colour..strips).step_by(2).collect();
+            if threads <= 1 || own.len() < 2
+            {
+                touches.extend(solve(&own));
+                continue;
+            }
+            
+            // Each worker takes a run of strips in order and they are joined
+            // in order, so the result is the same as solving them one by one
+            let chunk = own.len().div_ceil(threads);
+            thread::scope(|s|
+            {
+                let workers: Vec<_> = own.chunks(chunk).map(|run| s.spawn(move || solve(run))).collect();
+                for w in workers
+                {
+                    touches.extend(w.join().unwrap());
+                }
+            });
+        }
+        
+        // Pairs across the seam join the last strip to the first, so are
+        // solved last on their own
+        let balls = &mut self.balls;
+        self.grid.seam_pairs(|i, j|
+        {
+            let (a, b) = pair_mut(balls, i, j);
+            let touch = resolve_collisions(a, b, periodic.offset(b.location, a.location, bounds), impulses);
+            if record { touches.extend(touch.map(|t| t.between(i, j))); }
+        });
+        
+        return touches;
+    }
+    
+    // Updates sleep and emits contact events once for the whole step of
+    // length dt, from what touched in any of its substeps
+    fn finish_step(&mut self, dt: F)
+    {
+        // A pair touching in several substeps counts once, at its deepest
+        let mut touches = std::mem::take(&mut self.step_touches);
+        touches.sort_unstable_by(|a, b| a.pair().cmp(&b.pair()).then(b.depth.total_cmp(&a.depth)));
+        touches.dedup_by_key(|t| t.pair());
+        
+        if let Some(settings) = self.sleep
+        {
+            let handles = &self.handles;
+            let mut pairs: Vec<(usize, usize)> = touches.iter().map(|t| (t.i as usize, t.j as usize)).collect();
+            pairs.extend(self.links.iter().filter_map(|l| Some((handles.get(l.a)?, handles.get(l.b)?))));
+            for body in &self.bodies
+            {
+                pairs.extend(body.members.windows(2).filter_map(|m| Some((handles.get(m[0])?, handles.get(m[1])?))));
+            }
+            update_sleep(&mut self.balls, &pairs, settings, dt);
+        }
+        
+        if let Some(tracker) = &mut self.contacts
+        {
+            let handles = &self.handles;
+            tracker.update(touches.iter().map(|t| ContactEvent::new(
+                handles.handle(t.i as usize),
+                handles.handle(t.j as usize),
+                t.depth,
+                t.normal,
+                t.velocity
+            )).collect());
+        }
+        
+        // Kept to reuse its space next step
+        touches.clear();
+        self.step_touches = touches;
+    }
+    
+    // One step of length dt split into sub substeps. With merging on, balls
+    // that merged are removed at the very end, the only point a step can
+    // remove balls, so anything holding dense indices only needs to check
+    // the count after each step.
+    pub fn apply_phsyics_sub(&mut self, dt: F, sub: u8)
+    {
+        let step = dt;
+        let dt = dt / F::of(sub as f64);
+        
+        self.step_touches.clear();
         for _ in 0..sub
         {
             self.apply_phsyics(dt);
         }
+        self.finish_step(step);
+        if self.collisions == Collisions::Merge
+        {
+            self.merge_overlaps();
+        }
+        self.steps += 1;
+    }
+    
+    // Number of calls to apply_phsyics_sub so far
+    pub fn step_count(&self) -> u64
+    {
+        return self.steps;
+    }
+    
+    // Hash of everything that decides how the world carries on. Stepping is
+    // fully deterministic, with the same result for any thread count, so two
+    // runs given the same balls and inputs have the same hash after every step.
+    pub fn state_hash(&self) -> u64
+    {
+        let mut h = StateHasher::new();
+        h.write_u64(self.steps);
+        h.vec2(self.gravity);
+        h.real(self.drag.linear);
+        h.real(self.drag.quadratic);
+        h.real(self.drag.damping);
+        match self.gravitation
+        {
+            Some(g) =>
+            {
+                h.write_u8(1);
+                h.real(g.constant);
+                h.real(g.softening);
+                match g.method
+                {
+                    Method::Exact => h.write_u8(0),
+                    Method::BarnesHut { theta } =>
+                    {
+                        h.write_u8(1);
+                        h.real(theta);
+                    }
+                }
+            }
+            None => h.write_u8(0)
+        }
+        h.write_u8(self.collisions as u8);
+        h.write_u8(self.impulses as u8);
+        h.write_u8(self.ccd as u8);
+        match self.sleep
+        {
+            Some(settings) =>
+            {
+                h.write_u8(1);
+                h.real(settings.velocity);
+                h.write_u32(settings.steps);
+            }
+            None => h.write_u8(0)
+        }
+        h.write_u8(self.periodic.x as u8);
+        h.write_u8(self.periodic.y as u8);
+        for i in 0..4
+        {
+            h.real(self.bounds[i]);
+        }
+        self.container.hash_state(&mut h);
+        h.write_usize(self.obstacles.len());
+        for o in &self.obstacles
+        {
+            o.hash_state(&mut h);
+        }
+        h.write_usize(self.forces.len());
+        for f in &self.forces
+        {
+            f.hash_state(&mut h);
+        }
+        
+        for (handle, b) in self.iter()
+        {
+            handle.hash(&mut h);
+            b.hash_state(&mut h);
+        }
+        for l in &self.links
+        {
+            l.a.hash(&mut h);
+            l.b.hash(&mut h);
+            h.real(l.length);
+            match l.kind
+            {
+                LinkKind::Stick => h.write_u8(0),
+                LinkKind::Spring { stiffness, damping } =>
+                {
+                    h.write_u8(1);
+                    h.real(stiffness);
+                    h.real(damping);
+                }
+                LinkKind::Rope => h.write_u8(2)
+            }
+        }
+        for body in &self.bodies
+        {
+            h.write_usize(body.members.len());
+            for (m, q) in body.members.iter().zip(&body.rest)
+            {
+                m.hash(&mut h);
+                h.vec2(*q);
+            }
+            h.real(body.stiffness);
+        }
+        
+        return h.finish();
     }
     
-    pub fn new(bounds: Vec4) -> Physics
+    pub fn new(bounds: Vec4<F>) -> Physics<F>
     {
         return Physics {
             balls: Vec::with_capacity(100),
-            bounds: bounds
+            handles: Handles::new(),
+            links: Vec::new(),
+            bodies: Vec::new(),
+            obstacles: Vec::new(),
+            container: Container::Bounds,
+            bounds,
+            periodic: Periodic::NONE,
+            gravity: vec2(F::zero(), -F::of(GRAVITY.into())),
+            forces: Vec::new(),
+            drag: Drag::NONE,
+            gravitation: None,
+            tree: QuadTree::new(),
+            collisions: Collisions::Resolve,
+            grid: Grid::new(),
+            grid_stale: true,
+            threads: 1,
+            impulses: false,
+            ccd: false,
+            contacts: None,
+            step_touches: Vec::new(),
+            diagnose: false,
+            diagnostics: None,
+            sleep: None,
+            wake_pending: false,
+            steps: 0
         };
     }
     
-    pub fn add(&mut self, b: Ball)
+    // Most worker threads used per step. Small worlds use fewer, see
+    // MIN_PER_THREAD. 1 runs everything on the calling thread in a fixed order.
+    pub fn threads(&self) -> usize
+    {
+        return self.threads;
+    }
+    pub fn set_threads(&mut self, threads: usize)
     {
+        self.threads = threads.max(1);
+    }
+    
+    pub fn add(&mut self, mut b: Ball<F>) -> BallHandle
+    {
+        b.wake();
         self.balls.push(b);
+        self.grid_stale = true;
+        return self.handles.insert();
+    }
+    
+    // Removes the ball along with any links attached to it. Soft bodies it
+    // was part of keep their shape without it, unless too few balls are left.
+    pub fn remove(&mut self, h: BallHandle) -> Option<Ball<F>>
+    {
+        // The handles already point the last ball at slot i, so the balls
+        // have to be moved to match before anything is looked up
+        let i = self.handles.remove(h)?;
+        let removed = self.balls.swap_remove(i);
+        self.links.retain(|link| !link.touches(h));
+        for n in (0..self.bodies.len()).rev()
+        {
+            if !self.bodies[n].remove(h) { continue; }
+            
+            if self.bodies[n].members.len() < MIN_BODY_SIZE
+            {
+                self.bodies.swap_remove(n);
+                continue;
+            }
+            let members: Vec<usize> = self.bodies[n].members.iter().filter_map(|&m| self.handles.get(m)).collect();
+            let masses: Vec<F> = members.iter().map(|&m| self.balls[m].mass()).collect();
+            let rest = self.bodies[n].rest.clone();
+            self.bodies[n].set_rest(&rest, &masses);
+        }
+        self.grid_stale = true;
+        // Anything resting on the ball needs to fall
+        self.wake_pending = true;
+        return Some(removed);
+    }
+    
+    // Keeps only the balls f returns true for
+    pub fn retain<K: FnMut(BallHandle, &Ball<F>) -> bool>(&mut self, mut f: K)
+    {
+        // Backwards, so swap removal only moves balls that were already kept
+        for i in (0..self.balls.len()).rev()
+        {
+            let h = self.handles.handle(i);
+            if !f(h, &self.balls[i])
+            {
+                self.remove(h);
+            }
+        }
+    }
+    
+    pub fn contains(&self, h: BallHandle) -> bool
+    {
+        return self.handles.get(h).is_some();
+    }
+    pub fn get(&self, h: BallHandle) -> Option<&Ball<F>>
+    {
+        return self.balls.get(self.handles.get(h)?);
+    }
+    // Wakes the ball, as it may be about to be moved
+    pub fn get_mut(&mut self, h: BallHandle) -> Option<&mut Ball<F>>
+    {
+        self.grid_stale = true;
+        let b = self.balls.get_mut(self.handles.get(h)?)?;
+        b.wake();
+        return Some(b);
+    }
+    
+    // The axes that wrap as things stand, none unless the bounds are the
+    // container
+    pub fn wrapping(&self) -> Periodic
+    {
+        if let Container::Bounds = self.container { return self.periodic; }
+        return Periodic::NONE;
+    }
+    
+    fn fresh_grid(&mut self) -> &Grid<F>
+    {
+        if self.grid_stale
+        {
+            self.grid.build(&self.balls, self.bounds, self.wrapping());
+            self.grid_stale = false;
+        }
+        return &self.grid;
+    }
+    
+    // The ball under point, or the one with the closest centre if several are
+    pub fn pick(&mut self, point: Vec2<F>) -> Option<BallHandle>
+    {
+        return self.query_circle(point, F::zero()).first().copied();
+    }
+    
+    // Balls overlapping the rectangle from min to max
+    pub fn query_aabb(&mut self, min: Vec2<F>, max: Vec2<F>) -> Vec<BallHandle>
+    {
+        let mut found = Vec::new();
+        self.fresh_grid();
+        self.grid.region(min, max, |i|
+        {
+            let b = &self.balls[i];
+            let closest = vec2(b.location.x.clamp(min.x, max.x), b.location.y.clamp(min.y, max.y));
+            if (b.location - closest).magnitude2() <= b.radius * b.radius
+            {
+                found.push(self.handles.handle(i));
+            }
+        });
+        return found;
+    }
+    
+    // Balls overlapping the circle, nearest centre first
+    pub fn query_circle(&mut self, centre: Vec2<F>, radius: F) -> Vec<BallHandle>
+    {
+        let mut found = Vec::new();
+        let r = vec2(radius, radius);
+        self.fresh_grid();
+        self.grid.region(centre - r, centre + r, |i|
+        {
+            let b = &self.balls[i];
+            let dist = (b.location - centre).magnitude2();
+            let reach = b.radius + radius;
+            if dist <= reach * reach
+            {
+                found.push((dist, self.handles.handle(i)));
+            }
+        });
+        
+        found.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        return found.into_iter().map(|(_, h)| h).collect();
+    }
+    
+    // Every ball the ray hits within length, nearest first. A ray starting
+    // inside a ball hits it at distance 0.
+    pub fn raycast(&mut self, origin: Vec2<F>, dir: Vec2<F>, length: F) -> Vec<RayHit<F>>
+    {
+        let mut hits = Vec::new();
+        if dir == vec2(F::zero(), F::zero()) { return hits; }
+        
+        let dir = dir.normalize();
+        self.fresh_grid();
+        let grid = &self.grid;
+        grid.ray(origin, dir, length, |x, y|
+        {
+            // Balls can overlap into the cells around their own
+            for cx in x.saturating_sub(1)..(x + 2).min(grid.columns())
+            {
+                for cy in y.saturating_sub(1)..(y + 2).min(grid.rows())
+                {
+                    for &i in grid.cell(cx, cy)
+                    {
+                        let b = &self.balls[i as usize];
+                        let Some(t) = ray_circle(origin, dir, b.location, b.radius) else { continue; };
+                        if t > length { continue; }
+                        
+                        let point = origin + (dir * t);
+                        let normal = point - b.location;
+                        hits.push(RayHit {
+                            handle: self.handles.handle(i as usize),
+                            distance: t,
+                            point,
+                            normal: if normal == vec2(F::zero(), F::zero()) { -dir } else { normal.normalize() }
+                        });
+                    }
+                }
+            }
+        });
+        
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.handle.cmp(&b.handle)));
+        hits.dedup_by_key(|h| h.handle);
+        return hits;
+    }
+    
+    // Where the ball comes in iter, until balls are next added or removed
+    pub fn index(&self, h: BallHandle) -> Option<usize>
+    {
+        return self.handles.get(h);
+    }
+    
+    // Every ball alongside its handle
+    pub fn iter(&self) -> impl Iterator<Item = (BallHandle, &Ball<F>)>
+    {
+        return self.balls.iter().enumerate().map(|(i, b)| (self.handles.handle(i), b));
+    }
+    
+    // Returns false if either ball doesn't exist or they are the same ball
+    pub fn add_link(&mut self, link: Link<F>) -> bool
+    {
+        if link.a == link.b || !self.contains(link.a) || !self.contains(link.b) { return false; }
+        
+        self.wake(link.a);
+        self.wake(link.b);
+        self.links.push(link);
+        return true;
+    }
+    // Links two balls at their current distance apart
+    pub fn connect(&mut self, a: BallHandle, b: BallHandle, kind: LinkKind) -> bool
+    {
+        let (Some(ba), Some(bb)) = (self.get(a), self.get(b)) else { return false; };
+        let length = (ba.location - bb.location).magnitude();
+        return self.add_link(Link::new(a, b, length, kind));
+    }
+    // Removes any links between a and b, returning whether there were any
+    pub fn remove_link(&mut self, a: BallHandle, b: BallHandle) -> bool
+    {
+        let l = self.links.len();
+        self.links.retain(|link| !link.joins(a, b));
+        self.wake(a);
+        self.wake(b);
+        return self.links.len() != l;
+    }
+    // Removes every link attached to the ball
+    pub fn remove_links(&mut self, h: BallHandle)
+    {
+        self.links.retain(|link| !link.touches(h));
+        self.wake_pending = true;
+    }
+    pub fn links(&self) -> &[Link<F>]
+    {
+        return &self.links;
+    }
+    
+    // Returns false if any member doesn't exist, a ball is listed twice, the
+    // rest shape doesn't match the members or there are too few of them
+    pub fn add_body(&mut self, body: SoftBody<F>) -> bool
+    {
+        let n = body.members.len();
+        if n < MIN_BODY_SIZE || body.rest.len() != n || body.outline.iter().any(|&o| o >= n) { return false; }
+        if body.members.iter().enumerate().any(|(i, h)| !self.contains(*h) || body.members[..i].contains(h)) { return false; }
+        
+        for &h in &body.members
+        {
+            self.wake(h);
+        }
+        self.bodies.push(body);
+        return true;
+    }
+    // Makes the balls a soft body with its rest shape where they are now
+    pub fn connect_body(&mut self, members: Vec<BallHandle>, outline: Vec<usize>, stiffness: real, colour: Colour) -> bool
+    {
+        let balls: Option<Vec<&Ball<F>>> = members.iter().map(|&h| self.get(h)).collect();
+        let Some(balls) = balls else { return false; };
+        
+        let locations: Vec<Vec2<F>> = balls.iter().map(|b| b.location).collect();
+        let masses: Vec<F> = balls.iter().map(|b| b.mass()).collect();
+        let mut body = SoftBody::new(members, Vec::new(), outline, stiffness, colour);
+        body.set_rest(&locations, &masses);
+        return self.add_body(body);
+    }
+    pub fn bodies(&self) -> &[SoftBody<F>]
+    {
+        return &self.bodies;
     }
     
     pub fn count(&self) -> usize
@@ -118,21 +1238,391 @@ impl Physics {
         return self.balls.len();
     }
     
-    pub fn get_bounds(&self) -> Vec4
+    pub fn add_obstacle(&mut self, o: Obstacle<F>)
+    {
+        self.obstacles.push(o);
+        self.wake_all();
+    }
+    pub fn clear_obstacles(&mut self)
+    {
+        self.obstacles.clear();
+        self.wake_all();
+    }
+    pub fn obstacles(&self) -> &[Obstacle<F>]
+    {
+        return &self.obstacles;
+    }
+    
+    pub fn get_container(&self) -> &Container<F>
+    {
+        return &self.container;
+    }
+    pub fn set_container(&mut self, container: Container<F>)
+    {
+        self.container = container;
+        self.wake_all();
+    }
+    
+    pub fn get_bounds(&self) -> Vec4<F>
     {
         return self.bounds;
     }
-    pub fn set_bounds(&mut self, bounds: Vec4)
+    pub fn set_bounds(&mut self, bounds: Vec4<F>)
     {
         self.bounds = bounds;
+        self.grid_stale = true;
+        self.wake_all();
+    }
+    
+    // Whether contacts exchange momentum using the materials' restitution,
+    // rather than being pushed apart and then bounced by it.
+    pub fn impulses(&self) -> bool
+    {
+        return self.impulses;
+    }
+    pub fn set_impulses(&mut self, impulses: bool)
+    {
+        self.impulses = impulses;
+    }
+    
+    // Whether balls moving further than their radius in a substep are swept
+    // along their path, so they can't pass through anything
+    pub fn ccd(&self) -> bool
+    {
+        return self.ccd;
+    }
+    pub fn set_ccd(&mut self, ccd: bool)
+    {
+        self.ccd = ccd;
+    }
+    
+    // Which axes of the bounds wrap around. Only applies with the bounds as
+    // the container. Collisions, links and soft bodies reach across the
+    // seams, but gravitation, force generators and queries don't.
+    pub fn periodic(&self) -> Periodic
+    {
+        return self.periodic;
+    }
+    pub fn set_periodic(&mut self, periodic: Periodic)
+    {
+        self.periodic = periodic;
+        self.grid_stale = true;
+        self.wake_all();
+    }
+    
+    // Turns recording contact events on or off. Off forgets any contacts
+    // being tracked and events waiting to be drained.
+    pub fn set_contact_events(&mut self, enabled: bool)
+    {
+        self.contacts = if enabled { Some(ContactTracker::new()) } else { None };
+    }
+    pub fn contact_events(&self) -> bool
+    {
+        return self.contacts.is_some();
+    }
+    // Sends events straight to f instead of queueing them, if recording
+    pub fn set_contact_callback(&mut self, f: Option<ContactCallback<F>>)
+    {
+        if let Some(tracker) = &mut self.contacts
+        {
+            tracker.set_callback(f);
+        }
+    }
+    // Takes the queued events, from every step since the last drain
+    pub fn drain_contacts(&mut self) -> Vec<ContactEvent<F>>
+    {
+        return match &mut self.contacts
+        {
+            Some(tracker) => tracker.drain().collect(),
+            None => Vec::new()
+        };
+    }
+    
+    // Turns measuring energy, momentum and contacts after every step on or
+    // off. It costs a pass over the balls, more with gravitation on.
+    pub fn set_diagnostics(&mut self, enabled: bool)
+    {
+        self.diagnose = enabled;
+        self.diagnostics = None;
+    }
+    pub fn diagnostics_enabled(&self) -> bool
+    {
+        return self.diagnose;
+    }
+    // What the last step measured, if measuring
+    pub fn diagnostics(&self) -> Option<Diagnostics<F>>
+    {
+        return self.diagnostics;
+    }
+    
+    pub fn get_gravity(&self) -> Vec2<F>
+    {
+        return self.gravity;
+    }
+    pub fn set_gravity(&mut self, gravity: Vec2<F>)
+    {
+        if gravity != self.gravity
+        {
+            self.wake_all();
+        }
+        self.gravity = gravity;
+    }
+    
+    // Used for every ball without its own drag
+    pub fn get_drag(&self) -> Drag
+    {
+        return self.drag;
+    }
+    pub fn set_drag(&mut self, drag: Drag)
+    {
+        self.drag = drag;
+    }
+    
+    // Added on top of gravity, in the order they were added
+    pub fn add_force(&mut self, f: Box<dyn ForceGenerator<F>>)
+    {
+        self.forces.push(f);
+        self.wake_all();
+    }
+    pub fn clear_forces(&mut self)
+    {
+        self.forces.clear();
+        self.wake_all();
+    }
+    pub fn forces(&self) -> &[Box<dyn ForceGenerator<F>>]
+    {
+        return &self.forces;
+    }
+    
+    // Makes every ball pull every other towards it, None turns it off
+    pub fn gravitation(&self) -> Option<Gravitation>
+    {
+        return self.gravitation;
+    }
+    pub fn set_gravitation(&mut self, gravitation: Option<Gravitation>)
+    {
+        if gravitation != self.gravitation
+        {
+            self.wake_all();
+        }
+        self.gravitation = gravitation;
+    }
+    
+    pub fn collisions(&self) -> Collisions
+    {
+        return self.collisions;
+    }
+    pub fn set_collisions(&mut self, collisions: Collisions)
+    {
+        self.collisions = collisions;
+        self.wake_all();
+    }
+    
+    // Lets islands of balls that have settled stop being simulated, None
+    // keeps every ball awake.
+    pub fn sleeping(&self) -> Option<SleepSettings>
+    {
+        return self.sleep;
+    }
+    pub fn set_sleeping(&mut self, settings: Option<SleepSettings>)
+    {
+        self.sleep = settings;
+        if settings.is_none()
+        {
+            self.wake_all();
+        }
+    }
+    pub fn asleep_count(&self) -> usize
+    {
+        return self.balls.iter().filter(|b| b.is_asleep()).count();
+    }
+    pub fn wake(&mut self, h: BallHandle)
+    {
+        if let Some(i) = self.handles.get(h)
+        {
+            self.balls[i].wake();
+        }
+    }
+    pub fn wake_all(&mut self)
+    {
+        self.balls.iter_mut().for_each(Ball::wake);
     }
 }
 
-impl<'a> IntoIterator for &'a Physics {
-    type Item = &'a Ball;
-    type IntoIter = Iter<'a, Ball>;
+impl<'a, F: Float> IntoIterator for &'a Physics<F> {
+    type Item = &'a Ball<F>;
+    type IntoIter = Iter<'a, Ball<F>>;
 
     fn into_iter(self) -> Self::IntoIter {
         return self.balls.iter();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const STEPS: usize = 120;
+
+    // A busy world built the same way from the same seed every time
+    fn world(seed: u64, threads: usize) -> Physics
+    {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut p = Physics::new(vec4(-400.0, 400.0, 300.0, -300.0));
+        p.set_threads(threads);
+        p.set_impulses(true);
+        p.set_sleeping(Some(SleepSettings::default()));
+        p.add_obstacle(Obstacle::circle(vec2(0.0, -100.0), 60.0));
+        p.add_force(Box::new(Vortex::new(vec2(150.0, 100.0), 500.0, 150.0, Falloff::Linear)));
+
+        let area = vec2(-400.0, -300.0)..vec2(400.0, 300.0);
+        let handles: Vec<BallHandle> = (0..800).map(|_| p.add(Ball::random(&mut rng, &area, 2.0..6.0))).collect();
+        for pair in handles[..20].windows(2)
+        {
+            p.add_link(Link::new(pair[0], pair[1], 12.0, LinkKind::Stick));
+        }
+        let (points, outline) = SoftBody::ring(40.0, 5.0);
+        let members = points.iter().map(|&q| p.add(Ball::new(q + vec2(-200.0, 150.0), 5.0, colour(200, 80, 80)))).collect();
+        p.connect_body(members, outline, 0.5, colour(200, 80, 80));
+        return p;
+    }
+
+    #[test]
+    fn thread_count_doesnt_change_the_result()
+    {
+        let mut one = world(11, 1);
+        let mut many = world(11, 4);
+        assert_eq!(one.state_hash(), many.state_hash());
+        for step in 0..STEPS
+        {
+            one.apply_phsyics_sub(1.0 / 60.0, 4);
+            many.apply_phsyics_sub(1.0 / 60.0, 4);
+            assert_eq!(one.state_hash(), many.state_hash(), "diverged at step {}", step);
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_run()
+    {
+        let mut a = world(5, 1);
+        let mut b = world(5, 1);
+        for step in 0..STEPS
+        {
+            a.apply_phsyics_sub(1.0 / 60.0, 4);
+            b.apply_phsyics_sub(1.0 / 60.0, 4);
+            assert_eq!(a.state_hash(), b.state_hash(), "diverged at step {}", step);
+        }
+    }
+
+    #[test]
+    fn hash_covers_obstacles_forces_container_and_sleep()
+    {
+        let base = world(3, 1).state_hash();
+
+        let mut p = world(3, 1);
+        p.add_obstacle(Obstacle::segment(vec2(-50.0, 0.0), vec2(50.0, 0.0)));
+        assert_ne!(p.state_hash(), base);
+
+        let mut p = world(3, 1);
+        p.add_force(Box::new(Attractor::new(vec2(0.0, 0.0), 100.0, 50.0, Falloff::Constant)));
+        assert_ne!(p.state_hash(), base);
+
+        let mut p = world(3, 1);
+        p.set_container(Container::Circle { centre: vec2(0.0, 0.0), radius: 250.0 });
+        assert_ne!(p.state_hash(), base);
+
+        let mut p = world(3, 1);
+        p.set_sleeping(Some(SleepSettings { steps: 30, ..SleepSettings::default() }));
+        assert_ne!(p.state_hash(), base);
+    }
+
+    #[test]
+    fn contact_events_come_once_per_step()
+    {
+        let mut p = Physics::new(vec4(-100.0, 100.0, 100.0, -100.0));
+        p.set_contact_events(true);
+        let mut floor = Ball::new(vec2(0.0, 0.0), 10.0, colour(0, 0, 0));
+        floor.fixed = true;
+        p.add(floor);
+        p.add(Ball::new(vec2(0.0, 20.0), 10.0, colour(0, 0, 0)));
+
+        p.apply_phsyics_sub(1.0 / 60.0, 4);
+        let events = p.drain_contacts();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].phase, ContactPhase::Begin);
+
+        p.apply_phsyics_sub(1.0 / 60.0, 4);
+        let events = p.drain_contacts();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].phase, ContactPhase::Persist);
+    }
+
+    #[test]
+    fn settled_pile_sleeps_until_touched()
+    {
+        let mut p = Physics::new(vec4(-50.0, 50.0, 200.0, -50.0));
+        p.set_sleeping(Some(SleepSettings::default()));
+        for x in 0..5
+        {
+            p.add(Ball::new(vec2(-40.0 + (x as real * 20.0), -40.0), 10.0, colour(0, 0, 0)));
+        }
+        for _ in 0..300
+        {
+            p.apply_phsyics_sub(1.0 / 60.0, 4);
+        }
+        assert!(p.iter().all(|(_, b)| b.is_asleep()));
+
+        // Dropped on the middle ball, which should wake it
+        let dropped = p.add(Ball::new(vec2(0.0, 100.0), 10.0, colour(0, 0, 0)));
+        let mut woke = false;
+        for _ in 0..60
+        {
+            p.apply_phsyics_sub(1.0 / 60.0, 4);
+            woke |= p.iter().any(|(h, b)| h != dropped && !b.is_asleep());
+        }
+        assert!(woke);
+    }
+
+    #[test]
+    fn fast_balls_dont_pass_through_thin_obstacles()
+    {
+        let mut p = Physics::new(vec4(-200.0, 200.0, 200.0, -200.0));
+        p.set_gravity(vec2(0.0, 0.0));
+        p.set_ccd(true);
+        p.add_obstacle(Obstacle::segment(vec2(0.0, -50.0), vec2(0.0, 50.0)));
+        // Moving 60 units a substep, far more than its radius
+        let mut b = Ball::new(vec2(-100.0, 0.0), 3.0, colour(0, 0, 0));
+        b.set_velocity(vec2(60.0, 0.0));
+        let fast = p.add(b);
+
+        for _ in 0..10
+        {
+            p.apply_phsyics_sub(1.0 / 60.0, 4);
+            assert!(p.get(fast).unwrap().location.x < 0.0);
+        }
+    }
+
+    #[test]
+    fn fast_balls_only_hit_balls_when_resolving()
+    {
+        for collisions in [Collisions::Ignore, Collisions::Merge]
+        {
+            let mut p = Physics::new(vec4(-200.0, 200.0, 200.0, -200.0));
+            p.set_gravity(vec2(0.0, 0.0));
+            p.set_ccd(true);
+            p.set_collisions(collisions);
+            let mut b = Ball::new(vec2(-100.0, 0.0), 3.0, colour(0, 0, 0));
+            b.set_velocity(vec2(60.0, 0.0));
+            p.add(b);
+            // On the path, but never where the fast ball is at the end of
+            // a substep
+            let resting = p.add(Ball::new(vec2(0.0, 0.0), 3.0, colour(0, 0, 0)));
+
+            p.apply_phsyics_sub(1.0 / 60.0, 4);
+            assert_eq!(p.get(resting).unwrap().velocity(), vec2(0.0, 0.0));
+        }
+    }
+}
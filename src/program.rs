@@ -1,26 +1,59 @@
+use cgmath::InnerSpace;
 use cgmath::Matrix4;
 use cgmath::Vector2;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
 use util::BufferInitDescriptor;
 use util::DeviceExt;
 use wgpu::*;
 use wgpu_text::glyph_brush::ab_glyph::FontRef;
 use wgpu_text::glyph_brush::*;
 use winit::event::ElementState;
+use winit::event::KeyEvent;
 use winit::event::MouseButton;
 use winit::event::WindowEvent;
+use winit::keyboard::KeyCode;
+use winit::keyboard::PhysicalKey;
+use std::f32::consts::PI;
 use std::mem;
+use std::ops::Range;
+use std::path::Path;
+use std::thread;
 use std::time::Instant;
 use std::vec::Vec;
 use wgpu_text::{BrushBuilder, TextBrush};
 
 
 use crate::ball::*;
+use crate::constraint::*;
+use crate::container::*;
+use crate::diagnostics::*;
+use crate::events::*;
+use crate::force::*;
+use crate::periodic::*;
 use crate::physics::*;
+use crate::replay::*;
 use crate::maths::*;
 use crate::graphics::*;
+use crate::handle::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::nbody::*;
+use crate::obstacle::*;
+use crate::sleep::*;
+use crate::softbody::*;
+use crate::snapshot;
 use crate::state::*;
+use crate::timestep::*;
 use crate::helpers::*;
 
+// What the physics runs in, f64 if built with the f64 feature
+#[cfg(not(feature = "f64"))]
+type Sim = real;
+#[cfg(feature = "f64")]
+type Sim = f64;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 struct Vertex
@@ -65,6 +98,72 @@ const INDICES: &[u16] = &[
     2, 3, 0
 ];
 
+const STEP: f32 = 1.0 / 60.0;
+const SUB_STEPS: u8 = 4;
+// Most steps run in one frame before the simulation starts to slow down
+const MAX_STEPS: u32 = 4;
+
+// How far each arrow key press tilts gravity
+const TILT: f32 = PI / 12.0;
+
+const OBSTACLE_COLOUR: Colour = colour(110, 110, 120);
+const LINE_WIDTH: f32 = 2.0;
+
+const CHAIN_RADIUS: f32 = 4.0;
+const CHAIN_LENGTH: usize = 20;
+
+const TEXT_SNAPSHOT: &str = "snapshot.txt";
+const BINARY_SNAPSHOT: &str = "snapshot.bin";
+const REPLAY: &str = "replay.txt";
+
+const START_BALLS: usize = 100;
+
+// Attractors fall off with distance squared, so start much stronger
+const ATTRACTOR_STRENGTH: f32 = 20000.0;
+const FIELD_STRENGTH: f32 = 3000.0;
+const FIELD_RADIUS: f32 = 150.0;
+const DRAG: f32 = 0.5;
+const WIND_STRENGTH: f32 = 1500.0;
+const WIND_SIZE: Vec2 = vec2(300.0, 200.0);
+// A heavy fixed sun with a disc of small balls orbiting it
+const GALAXY_BALLS: usize = 400;
+const SUN_MASS: f32 = 200000.0;
+const SUN_RADIUS: f32 = 12.0;
+const DISC: Range<f32> = 60.0..300.0;
+const BLOB_RADIUS: f32 = 40.0;
+const BLOB_BALL_RADIUS: f32 = 5.0;
+const BLOB_STIFFNESS: f32 = 0.1;
+// Diagnostics graph beside the text, in pixels from the middle of the window
+const GRAPH_STEPS: usize = 240;
+const GRAPH_POS: Vec2 = vec2(80.0, -40.0);
+const GRAPH_SIZE: Vec2 = vec2(240.0, 80.0);
+const GRAPH_FRAME: Colour = colour(80, 80, 90);
+const ENERGY_COLOUR: Colour = colour(240, 240, 240);
+const KINETIC_COLOUR: Colour = colour(240, 160, 60);
+const MOMENTUM_COLOUR: Colour = colour(90, 200, 120);
+const PENETRATION_COLOUR: Colour = colour(220, 70, 70);
+// One of the numbers the graph plots
+type Measure = fn(&Diagnostics<Sim>) -> f32;
+
+// What a left click does
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Tool
+{
+    Spawn,
+    Attractor,
+    Repulsor,
+    Vortex,
+    Wind
+}
+
+const TOOLS: [(&str, Tool); 5] = [
+    ("Spawn", Tool::Spawn),
+    ("Attractor", Tool::Attractor),
+    ("Repulsor", Tool::Repulsor),
+    ("Vortex", Tool::Vortex),
+    ("Wind", Tool::Wind)
+];
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 struct Uniform
@@ -96,12 +195,13 @@ impl Instance
         }
     }
     
-    const fn from_ball(b: Ball) -> Instance
+    // Where the physics stops and drawing starts, so it's all f32 from here
+    fn from_ball<F: Float>(b: &Ball<F>) -> Instance
     {
         return Instance {
             colour: c_to_v(b.colour),
-            location: b.location,
-            radius: b.radius
+            location: cast2(b.location),
+            radius: b.radius.as_f32()
         };
     }
 }
@@ -122,18 +222,42 @@ pub struct Program<'a>
 {
     render_pipeline: RenderPipeline,
     draw_object: DrawObject,
+    shape_pipeline: RenderPipeline,
+    scene_object: Option<DrawObject>,
+    scene_changed: bool,
+    frame_object: Option<DrawObject>,
     uniform_buffer: Buffer,
     uniform_data: Uniform,
     bind_group: BindGroup,
     instances: Vec<Instance>,
     instance_buffer: Buffer,
+    // Where each ball is drawn this frame
+    drawn: Vec<Vec2>,
+    // Which balls are part of a soft body, kept to save allocating per frame
+    in_body: Vec<bool>,
     
     text_manager: TextBrush<FontRef<'a>>,
     text: OwnedSection,
-    physics: Physics,
+    physics: Physics<Sim>,
+    timestep: Timestep,
+    last_frame: Instant,
+    // Ball locations before the latest step, for interpolation
+    previous: Vec<Vec2<Sim>>,
     click: bool,
     m_pos: Vec2,
-    rand: rand::rngs::ThreadRng
+    material: usize,
+    tool: usize,
+    // Everything random comes from rand, so a run can be repeated by
+    // starting with the same seed
+    seed: u64,
+    rand: StdRng,
+    // Inputs waiting for the next step, so they land on the same step when
+    // replayed
+    pending: Vec<Input>,
+    window_bounds: Vec4,
+    recording: Option<Recording>,
+    playback: Option<Playback>,
+    history: History<Sim>
 }
 
 impl<'a> WinFunc for Program<'a>
@@ -142,15 +266,12 @@ impl<'a> WinFunc for Program<'a>
     fn new(device: &Device, config: &SurfaceConfiguration) -> Self
     {   
         let instances = Vec::with_capacity(100);
-        let mut physics = Physics::new(size_bounds(config.width as f32, config.height as f32));
-        let mut rand = rand::rng();
-        
-        let bounds = physics.get_bounds();
-        for _ in 0..100
-        {
-            let b = ball(&mut rand, bounds);
-            physics.add(b);
-        }
+        let bounds = size_bounds(config.width as f32, config.height as f32);
+        let seed = seed_arg().unwrap_or_else(rand::random);
+        log::info!("Seed {}", seed);
+        let mut rand = StdRng::seed_from_u64(seed);
+        let mut physics = start_world(&mut rand, cast4(bounds));
+        physics.set_threads(thread::available_parallelism().map_or(1, |n| n.get()));
         
         let uniform_data = Uniform {
             matrix: Matrix4::from_scale(1.0)
@@ -208,6 +329,10 @@ impl<'a> WinFunc for Program<'a>
         
         let draw_object = DrawObject::new(&device, VERTICES, INDICES);
         
+        let shape_shader = device.create_shader_module(include_wgsl!("shape.wgsl"));
+        let shape_pipeline = pipeline![device, render_pipeline_layout, shape_shader, config;
+            ShapeVertex::desc()];
+        
         let font = include_bytes!("Nunito.ttf");
         let brush = BrushBuilder::using_font_bytes(font).unwrap()
             .build(device, config.width, config.height, config.format);
@@ -222,18 +347,35 @@ impl<'a> WinFunc for Program<'a>
         return Self {
             render_pipeline,
             draw_object,
+            shape_pipeline,
+            scene_object: None,
+            scene_changed: false,
+            frame_object: None,
             uniform_buffer,
             uniform_data,
             bind_group: uniform_bind_group,
             instances,
             instance_buffer,
+            drawn: Vec::new(),
+            in_body: Vec::new(),
             
             text_manager: brush,
             text: section,
             physics,
+            timestep: Timestep::new(STEP, MAX_STEPS),
+            last_frame: Instant::now(),
+            previous: Vec::new(),
             click: false,
             m_pos: vec2(0.0, 0.0),
-            rand
+            material: 0,
+            tool: 0,
+            seed,
+            rand,
+            pending: Vec::new(),
+            window_bounds: bounds,
+            recording: None,
+            playback: None,
+            history: History::new(GRAPH_STEPS)
         };
     }
 
@@ -247,7 +389,8 @@ impl<'a> WinFunc for Program<'a>
             matrix: OPENGL_TO_WGPU_MATRIX * m
         };
         
-        self.physics.set_bounds(size_bounds(size.x, size.y));
+        self.window_bounds = size_bounds(size.x, size.y);
+        self.queue(Input::Resize(size));
         self.text_manager.resize_view(size.x, size.y, &source.queue);
         
         self.text.screen_position = (size.x * 0.5, size.y * 0.5);
@@ -259,42 +402,174 @@ impl<'a> WinFunc for Program<'a>
         {
             WindowEvent::MouseInput { device_id: _, state, button } =>
             {
-                if *button == MouseButton::Left
+                let button = match button
                 {
-                    self.click = *state == ElementState::Pressed;
-                }
-                
+                    MouseButton::Left => Button::Left,
+                    MouseButton::Right => Button::Right,
+                    _ => return false
+                };
+                self.queue(if *state == ElementState::Pressed { Input::Press(button) } else { Input::Release(button) });
                 return true;
             }
             WindowEvent::CursorMoved { device_id: _, position } =>
             {
                 let p = vec2(position.x as f32, position.y as f32);
                 let s = vec2(source.size.width as f32, source.size.height as f32);
-                self.m_pos = vec2(p.x - (s.x * 0.5), (s.y * 0.5) - p.y);
+                self.queue(Input::Cursor(vec2(p.x - (s.x * 0.5), (s.y * 0.5) - p.y)));
                 return true;
             }
+            WindowEvent::KeyboardInput { event: KeyEvent {
+                state: ElementState::Pressed,
+                physical_key: PhysicalKey::Code(code),
+                ..
+            }, .. } => self.key(*code),
             _ => false
         };
     }
 
     fn update(&mut self, source: &State<Self>)
     {
-        if self.click
+        let t = Instant::now();
+        let frame_time = t.duration_since(self.last_frame);
+        self.last_frame = t;
+        
+        let steps = self.timestep.advance(frame_time.as_secs_f32());
+        for _ in 0..steps
         {
-            let b = vec4(self.m_pos.x, self.m_pos.x + 0.1, self.m_pos.y + 0.1, self.m_pos.y);
-            self.physics.add(ball(&mut self.rand, b));
+            let step = self.physics.step_count();
+            let inputs = match &mut self.playback
+            {
+                Some(p) => p.take(step),
+                None => mem::take(&mut self.pending)
+            };
+            for input in inputs
+            {
+                if let Some(r) = &mut self.recording
+                {
+                    r.push(step, input);
+                }
+                self.apply(input);
+            }
+            
+            if self.click
+            {
+                let p = self.cursor();
+                let b = vec4(p.x, p.x + 0.1, p.y + 0.1, p.y);
+                let (_, m) = Material::PRESETS[self.material];
+                self.physics.add(ball(&mut self.rand, b, m));
+            }
+            
+            self.previous.clear();
+            self.previous.extend(self.physics.into_iter().map(|b| b.location));
+            self.physics.apply_phsyics_sub(self.timestep.step() as Sim, SUB_STEPS);
+            // Merging removes balls, which moves others to different indices
+            if self.physics.count() < self.previous.len()
+            {
+                self.previous.clear();
+            }
+            if let Some(d) = self.physics.diagnostics()
+            {
+                self.history.push(d);
+            }
+            if log::log_enabled!(log::Level::Debug)
+            {
+                log::debug!("Step {} hash {:016x}", self.physics.step_count(), self.physics.state_hash());
+            }
+            
+            if self.playback.as_ref().is_some_and(|p| p.finished(self.physics.step_count()))
+            {
+                self.stop_playback();
+            }
         }
-        
-        let t = Instant::now();
-        self.physics.apply_phsyics_sub(1.0 / 60.0, 4);
         let dt = Instant::now().duration_since(t);
         
-        fill_buffer(&self.physics, &mut self.instances);
+        fill_buffer(&self.physics, &self.previous, self.timestep.alpha(), &mut self.instances, &mut self.drawn, &mut self.in_body);
+        
+        // Soft bodies and the graph change every frame, so are rebuilt every frame
+        let mut mesh = body_mesh(&self.physics, &self.drawn);
+        if self.physics.diagnostics_enabled()
+        {
+            graph_mesh(&mut mesh, &self.history);
+        }
+        match &mut self.frame_object
+        {
+            Some(o) => o.write(&source.device, &source.queue, &mesh.vertices, &mesh.indices),
+            None if !mesh.is_empty() => self.frame_object = Some(DrawObject::new(&source.device, &mesh.vertices, &mesh.indices)),
+            None => {}
+        }
+        
+        if self.scene_changed
+        {
+            let mesh = scene_mesh(&self.physics);
+            self.scene_object = if mesh.is_empty() { None }
+                else { Some(DrawObject::new(&source.device, &mesh.vertices, &mesh.indices)) };
+            self.scene_changed = false;
+        }
         
-        let s = self.instances.len().to_string();
+        let s = self.physics.count().to_string();
         self.text.text.clear();
         self.text.text.push(text((dt.as_secs_f32() * 1000_f32).to_string() + "\n"));
-        self.text.text.push(text(s));
+        self.text.text.push(text(s + "\n"));
+        self.text.text.push(text(Material::PRESETS[self.material].0.to_string()));
+        if TOOLS[self.tool].1 != Tool::Spawn
+        {
+            self.text.text.push(text(format!("\n{}", TOOLS[self.tool].0)));
+        }
+        self.text.text.push(text(format!("\nseed {}", self.seed)));
+        if self.physics.get_drag() != Drag::NONE
+        {
+            self.text.text.push(text("\nair drag".to_string()));
+        }
+        if self.physics.gravitation().is_some()
+        {
+            self.text.text.push(text("\ngravitation".to_string()));
+        }
+        if self.physics.ccd()
+        {
+            self.text.text.push(text("\nswept".to_string()));
+        }
+        match self.physics.periodic()
+        {
+            Periodic { x: false, y: false } => {}
+            Periodic { x: true, y: false } => self.text.text.push(text("\nwrap sideways".to_string())),
+            Periodic { x: false, y: true } => self.text.text.push(text("\nwrap vertically".to_string())),
+            Periodic { x: true, y: true } => self.text.text.push(text("\nwrap all round".to_string()))
+        }
+        match self.physics.collisions()
+        {
+            Collisions::Resolve => {}
+            Collisions::Merge => self.text.text.push(text("\nmerging".to_string())),
+            Collisions::Ignore => self.text.text.push(text("\nno collisions".to_string()))
+        }
+        if self.recording.is_some()
+        {
+            self.text.text.push(text("\nrecording".to_string()));
+        }
+        if self.playback.is_some()
+        {
+            self.text.text.push(text("\nreplaying".to_string()));
+        }
+        
+        if self.physics.contact_events()
+        {
+            let begun = self.physics.drain_contacts().iter()
+                .filter(|e| e.phase == ContactPhase::Begin)
+                .count();
+            self.text.text.push(text(format!("\n{} new contacts", begun)));
+        }
+        if self.physics.sleeping().is_some()
+        {
+            self.text.text.push(text(format!("\n{} asleep", self.physics.asleep_count())));
+        }
+        if let Some(d) = self.history.latest()
+        {
+            // Height along a wrapped axis isn't counted, see Physics::measure
+            let wrapped = self.physics.wrapping();
+            let height = if wrapped.x || wrapped.y { " without wrapped height" } else { "" };
+            self.text.text.push(text(format!("\nenergy {:.0}{}, kinetic {:.0}", d.energy(), height, d.kinetic)));
+            self.text.text.push(text(format!("\nmomentum {:.0}, {:.0}", d.momentum.x, d.momentum.y)));
+            self.text.text.push(text(format!("\n{} touching, {:.2} deepest", d.contacts, d.max_penetration)));
+        }
         
         if self.instance_buffer.size() < (self.instances.len() * mem::size_of::<Instance>()) as u64
         {
@@ -340,8 +615,19 @@ impl<'a> WinFunc for Program<'a>
             timestamp_writes: None,
         });
         
-        render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
+        
+        render_pass.set_pipeline(&self.shape_pipeline);
+        if let Some(o) = &self.scene_object
+        {
+            o.draw(&mut render_pass, 1);
+        }
+        if let Some(o) = &self.frame_object
+        {
+            o.draw(&mut render_pass, 1);
+        }
+        
+        render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
         self.draw_object.draw(&mut render_pass, self.instances.len() as u32);
         
@@ -349,21 +635,634 @@ impl<'a> WinFunc for Program<'a>
     }
 }
 
-fn fill_buffer(balls: &Physics, inst: &mut Vec<Instance>)
+impl<'a> Program<'a>
 {
-    if inst.len() != balls.count()
+    fn key(&mut self, code: KeyCode) -> bool
+    {
+        let action = match code
+        {
+            KeyCode::ArrowLeft => Action::TiltLeft,
+            KeyCode::ArrowRight => Action::TiltRight,
+            KeyCode::ArrowUp => Action::FlipGravity,
+            KeyCode::ArrowDown => Action::ResetGravity,
+            KeyCode::KeyB => Action::NextContainer,
+            KeyCode::KeyC => Action::Chain,
+            KeyCode::KeyR => Action::Bridge,
+            KeyCode::KeyE => Action::ContactEvents,
+            KeyCode::KeyG => Action::Galton,
+            KeyCode::KeyX => Action::Clear,
+            KeyCode::KeyI => Action::Impulses,
+            KeyCode::KeyM => Action::NextMaterial,
+            KeyCode::KeyZ => Action::Sleep,
+            KeyCode::KeyT => Action::NextTool,
+            KeyCode::KeyD => Action::Drag,
+            KeyCode::KeyK => Action::ClearForces,
+            KeyCode::KeyA => Action::Air,
+            KeyCode::KeyN => Action::Galaxy,
+            KeyCode::KeyP => Action::Collisions,
+            KeyCode::KeyO => Action::Ring,
+            KeyCode::KeyL => Action::Blob,
+            KeyCode::KeyV => Action::Ccd,
+            KeyCode::KeyW => Action::Periodic,
+            
+            // Not part of the world, so never recorded
+            KeyCode::F2 => return self.toggle_recording(),
+            KeyCode::F3 => return self.toggle_playback(),
+            KeyCode::F4 =>
+            {
+                self.physics.set_diagnostics(!self.physics.diagnostics_enabled());
+                self.history.clear();
+                return true;
+            }
+            _ if self.playback.is_some() => return false,
+            KeyCode::F5 => return self.save(TEXT_SNAPSHOT, snapshot::Format::Text),
+            KeyCode::F6 => return self.save(BINARY_SNAPSHOT, snapshot::Format::Binary),
+            KeyCode::F9 => return self.load(TEXT_SNAPSHOT),
+            KeyCode::F10 => return self.load(BINARY_SNAPSHOT),
+            _ => return false
+        };
+        
+        self.queue(Input::Action(action));
+        return true;
+    }
+    
+    // Where the mouse is, in the physics' float type
+    fn cursor(&self) -> Vec2<Sim>
+    {
+        return cast2(self.m_pos);
+    }
+    
+    // Holds onto input until the next step, ignoring it while replaying
+    fn queue(&mut self, input: Input)
+    {
+        if self.playback.is_some() { return; }
+        
+        // Only where the cursor ends up before the step matters
+        if let (Input::Cursor(p), Some(Input::Cursor(last))) = (input, self.pending.last_mut())
+        {
+            *last = p;
+            return;
+        }
+        self.pending.push(input);
+    }
+    
+    fn apply(&mut self, input: Input)
+    {
+        match input
+        {
+            Input::Cursor(p) => self.m_pos = p,
+            Input::Press(Button::Left) => match TOOLS[self.tool].1
+            {
+                Tool::Spawn => self.click = true,
+                tool => self.place_force(tool)
+            },
+            Input::Release(Button::Left) => self.click = false,
+            Input::Press(Button::Right) =>
+            {
+                if let Some(h) = self.physics.pick(self.cursor())
+                {
+                    self.physics.remove(h);
+                    self.previous.clear();
+                }
+            }
+            Input::Release(Button::Right) => {}
+            Input::Resize(size) => self.physics.set_bounds(cast4(size_bounds(size.x, size.y))),
+            Input::Action(a) => self.act(a)
+        }
+    }
+    
+    fn act(&mut self, action: Action)
     {
-        inst.resize(balls.count(), Instance::default());
+        let g = self.physics.get_gravity();
+        match action
+        {
+            Action::TiltLeft => self.physics.set_gravity(rotate(g, -TILT as Sim)),
+            Action::TiltRight => self.physics.set_gravity(rotate(g, TILT as Sim)),
+            Action::FlipGravity => self.physics.set_gravity(-g),
+            Action::ResetGravity => self.physics.set_gravity(vec2(0.0, -GRAVITY as Sim)),
+            Action::NextContainer => self.next_container(),
+            Action::Chain => self.spawn_chain(),
+            Action::Bridge => self.spawn_bridge(),
+            Action::ContactEvents => self.physics.set_contact_events(!self.physics.contact_events()),
+            Action::Galton => self.toggle_galton(),
+            Action::Clear =>
+            {
+                self.physics.retain(|_, _| false);
+                self.previous.clear();
+            }
+            Action::Impulses => self.physics.set_impulses(!self.physics.impulses()),
+            Action::NextMaterial => self.material = (self.material + 1) % Material::PRESETS.len(),
+            Action::Sleep =>
+            {
+                let sleep = match self.physics.sleeping()
+                {
+                    Some(_) => None,
+                    None => Some(SleepSettings::default())
+                };
+                self.physics.set_sleeping(sleep);
+            }
+            Action::NextTool => self.tool = (self.tool + 1) % TOOLS.len(),
+            Action::Drag => self.physics.add_force(Box::new(LinearDrag::new(DRAG))),
+            Action::Air =>
+            {
+                let drag = if self.physics.get_drag() == Drag::NONE { Drag::AIR } else { Drag::NONE };
+                self.physics.set_drag(drag);
+            }
+            Action::ClearForces =>
+            {
+                self.physics.clear_forces();
+                self.scene_changed = true;
+            }
+            Action::Galaxy => self.toggle_galaxy(),
+            Action::Collisions =>
+            {
+                let next = match self.physics.collisions()
+                {
+                    Collisions::Resolve => Collisions::Merge,
+                    Collisions::Merge => Collisions::Ignore,
+                    Collisions::Ignore => Collisions::Resolve
+                };
+                self.physics.set_collisions(next);
+            }
+            Action::Ring => self.spawn_body(false),
+            Action::Blob => self.spawn_body(true),
+            Action::Ccd => self.physics.set_ccd(!self.physics.ccd()),
+            Action::Periodic =>
+            {
+                let next = match self.physics.periodic()
+                {
+                    Periodic { x: false, y: false } => Periodic::new(true, false),
+                    Periodic { x: true, y: false } => Periodic::new(false, true),
+                    Periodic { x: false, y: true } => Periodic::new(true, true),
+                    Periodic { x: true, y: true } => Periodic::NONE
+                };
+                self.physics.set_periodic(next);
+            }
+        }
+    }
+    
+    // Drops a soft body at the cursor, either a ring of balls or a filled disc
+    fn spawn_body(&mut self, filled: bool)
+    {
+        let (radius, ball_radius) = (BLOB_RADIUS as Sim, BLOB_BALL_RADIUS as Sim);
+        let (points, outline) = if filled { SoftBody::disc(radius, ball_radius) }
+            else { SoftBody::ring(radius, ball_radius) };
+        let (_, m) = Material::PRESETS[self.material];
+        let c = colour(self.rand.random(), self.rand.random(), self.rand.random());
+        let members = points.iter()
+            .map(|p| self.physics.add(Ball::new_material(Ball::new(self.cursor() + p, ball_radius, c), m)))
+            .collect();
+        self.physics.connect_body(members, outline, BLOB_STIFFNESS, c);
     }
     
-    let mut i = 0;
-    for b in balls
+    // Turns on gravitation between the balls, without the world's gravity,
+    // and sets a galaxy spinning around the cursor. Again turns it back off.
+    fn toggle_galaxy(&mut self)
     {
-        inst[i] = Instance::from_ball(*b);
-        i += 1;
+        if self.physics.gravitation().is_some()
+        {
+            self.physics.set_gravitation(None);
+            self.physics.set_gravity(vec2(0.0, -GRAVITY as Sim));
+            return;
+        }
+        
+        let g = Gravitation::default();
+        self.physics.set_gravitation(Some(g));
+        self.physics.set_gravity(vec2(0.0, 0.0));
+        
+        let centre = self.cursor();
+        let mut sun = Ball::new(centre, SUN_RADIUS as Sim, colour(255, 220, 120));
+        sun.mass = Some(SUN_MASS as Sim);
+        sun.fixed = true;
+        self.physics.add(sun);
+        
+        // Circular orbits, counter-clockwise, ignoring the disc's own mass
+        let dt = (STEP / SUB_STEPS as f32) as Sim;
+        for _ in 0..GALAXY_BALLS
+        {
+            let r = self.rand.random_range(DISC) as Sim;
+            let angle = self.rand.random_range(0.0..(PI * 2.0)) as Sim;
+            let dir = vec2(angle.cos(), angle.sin());
+            let p = centre + (dir * r);
+            let mut b = Ball::random(&mut self.rand, &(p..(p + vec2(0.1, 0.1))), 1.0..3.0);
+            let speed = (g.constant as Sim * SUN_MASS as Sim / r).sqrt();
+            b.set_velocity(vec2(-dir.y, dir.x) * (speed * dt));
+            self.physics.add(b);
+        }
+    }
+    
+    // Puts a force field of the tool's kind at the cursor
+    fn place_force(&mut self, tool: Tool)
+    {
+        let p = self.cursor();
+        let (radius, strength) = (FIELD_RADIUS as Sim, FIELD_STRENGTH as Sim);
+        let wind: Vec2<Sim> = cast2(WIND_SIZE * 0.5);
+        let f: Box<dyn ForceGenerator<Sim>> = match tool
+        {
+            Tool::Spawn => return,
+            Tool::Attractor => Box::new(Attractor::new(p, ATTRACTOR_STRENGTH as Sim, radius, Falloff::InverseSquare)),
+            Tool::Repulsor => Box::new(Attractor::repulsor(p, strength, radius, Falloff::Linear)),
+            Tool::Vortex => Box::new(Vortex::new(p, strength, radius, Falloff::Constant)),
+            Tool::Wind => Box::new(Wind::new(p - wind, p + wind, vec2(WIND_STRENGTH as Sim, 0.0)))
+        };
+        self.physics.add_force(f);
+        self.scene_changed = true;
+    }
+    
+    // Starts again from a fresh world, as the game does on launch
+    fn restart(&mut self, seed: u64, bounds: Vec4<Sim>)
+    {
+        self.seed = seed;
+        self.rand = StdRng::seed_from_u64(seed);
+        let mut physics = start_world(&mut self.rand, bounds);
+        physics.set_threads(self.physics.threads());
+        physics.set_diagnostics(self.physics.diagnostics_enabled());
+        self.physics = physics;
+        self.history.clear();
+        self.material = 0;
+        self.tool = 0;
+        self.click = false;
+        self.pending.clear();
+        self.previous.clear();
+        self.scene_changed = true;
+    }
+    
+    // Starting restarts the world with a new seed, stopping saves the replay
+    fn toggle_recording(&mut self) -> bool
+    {
+        if self.playback.is_some() { return false; }
+        
+        match self.recording.take()
+        {
+            Some(mut r) =>
+            {
+                r.end = Some((self.physics.step_count(), self.physics.state_hash()));
+                match r.save(Path::new(REPLAY))
+                {
+                    Ok(()) => log::info!("Saved {}", REPLAY),
+                    Err(e) => log::error!("Couldn't save {}: {}", REPLAY, e)
+                }
+            }
+            None =>
+            {
+                let seed = rand::random();
+                self.restart(seed, cast4(self.window_bounds));
+                // Cursor isn't known until it moves, so start with it
+                let mut r = Recording::new(seed, self.window_bounds);
+                r.push(0, Input::Cursor(self.m_pos));
+                self.recording = Some(r);
+            }
+        }
+        return true;
+    }
+    
+    fn toggle_playback(&mut self) -> bool
+    {
+        if self.playback.is_some()
+        {
+            self.stop_playback();
+            return true;
+        }
+        if self.recording.is_some() { return false; }
+        
+        let recording = match Recording::load(Path::new(REPLAY))
+        {
+            Ok(r) => r,
+            Err(e) =>
+            {
+                log::error!("Couldn't load {}: {}", REPLAY, e);
+                return true;
+            }
+        };
+        self.restart(recording.seed, cast4(recording.bounds));
+        self.playback = Some(Playback::new(recording));
+        return true;
+    }
+    
+    // Checks the world ended up as recorded, then hands control back
+    fn stop_playback(&mut self)
+    {
+        let Some(p) = self.playback.take() else { return; };
+        
+        let step = self.physics.step_count();
+        match p.recording().end
+        {
+            Some((end, hash)) if end == step && hash == self.physics.state_hash() =>
+                log::info!("Replay matched at step {}", step),
+            Some((end, _)) if end == step => log::warn!("Replay diverged by step {}", step),
+            _ => log::info!("Replay stopped at step {}", step)
+        }
+        self.physics.set_bounds(cast4(self.window_bounds));
+        self.click = false;
+    }
+    
+    // Cycles through the container shapes, sized to the current window
+    fn next_container(&mut self)
+    {
+        let b = self.physics.get_bounds();
+        let w = b.y - b.x;
+        let h = b.z - b.w;
+        let centre = vec2((b.x + b.y) * 0.5, (b.z + b.w) * 0.5);
+        
+        let next = match self.physics.get_container()
+        {
+            Container::Bounds => Container::Circle { centre, radius: w.min(h) * 0.45 },
+            Container::Circle { .. } => Container::Capsule {
+                a: centre - vec2(w * 0.25, 0.0),
+                b: centre + vec2(w * 0.25, 0.0),
+                radius: h * 0.3
+            },
+            // An hourglass
+            Container::Capsule { .. } => Container::Polygon(vec![
+                centre + vec2(-w * 0.3, -h * 0.45),
+                centre + vec2(w * 0.3, -h * 0.45),
+                centre + vec2(w * 0.02, 0.0),
+                centre + vec2(w * 0.3, h * 0.45),
+                centre + vec2(-w * 0.3, h * 0.45),
+                centre + vec2(-w * 0.02, 0.0)
+            ]),
+            Container::Polygon(_) => Container::Bounds
+        };
+        
+        self.physics.set_container(next);
+        self.scene_changed = true;
+    }
+    
+    // Fills the window with a funnel, rows of pegs and collecting bins, or
+    // clears them if there are already obstacles
+    fn toggle_galton(&mut self)
+    {
+        self.scene_changed = true;
+        if !self.physics.obstacles().is_empty()
+        {
+            self.physics.clear_obstacles();
+            return;
+        }
+        
+        let b = self.physics.get_bounds();
+        let w = b.y - b.x;
+        let h = b.z - b.w;
+        let gap = 12.0;
+        let spacing = 30.0;
+        
+        let funnel_top = b.z - (h * 0.05);
+        let funnel_bottom = b.z - (h * 0.25);
+        self.physics.add_obstacle(Obstacle::segment(vec2(b.x + (w * 0.1), funnel_top), vec2(-gap, funnel_bottom)));
+        self.physics.add_obstacle(Obstacle::segment(vec2(b.y - (w * 0.1), funnel_top), vec2(gap, funnel_bottom)));
+        
+        let bin_top = b.w + (h * 0.3);
+        let mut y = funnel_bottom - spacing;
+        let mut row = 0;
+        while y > bin_top + spacing
+        {
+            let offset = if row % 2 == 0 { 0.0 } else { spacing * 0.5 };
+            let mut x = offset - (spacing * (w / spacing / 2.0).floor());
+            while x < b.y
+            {
+                self.physics.add_obstacle(Obstacle::circle(vec2(x, y), 4.0));
+                x += spacing;
+            }
+            y -= spacing;
+            row += 1;
+        }
+        
+        let mut x = -(spacing * (w / spacing / 2.0).floor());
+        while x < b.y
+        {
+            self.physics.add_obstacle(Obstacle::polygon(vec![
+                vec2(x - 2.0, b.w), vec2(x + 2.0, b.w),
+                vec2(x + 2.0, bin_top), vec2(x - 2.0, bin_top)]));
+            x += spacing;
+        }
+    }
+    
+    fn save(&self, path: &str, format: snapshot::Format) -> bool
+    {
+        match snapshot::save(&self.physics, Path::new(path), format)
+        {
+            Ok(()) => log::info!("Saved {}", path),
+            Err(e) => log::error!("Couldn't save {}: {}", path, e)
+        }
+        return true;
+    }
+    
+    // Replaces the world with the snapshot, keeping the current window size
+    // and settings that aren't part of the world
+    fn load(&mut self, path: &str) -> bool
+    {
+        let mut physics = match snapshot::load(Path::new(path))
+        {
+            Ok(p) => p,
+            Err(e) =>
+            {
+                log::error!("Couldn't load {}: {}", path, e);
+                return true;
+            }
+        };
+        // A replay can't reproduce a world loaded from outside it
+        if self.recording.take().is_some()
+        {
+            log::warn!("Recording stopped by loading {}", path);
+        }
+        
+        physics.set_bounds(self.physics.get_bounds());
+        physics.set_threads(self.physics.threads());
+        physics.set_sleeping(self.physics.sleeping());
+        physics.set_contact_events(self.physics.contact_events());
+        physics.set_diagnostics(self.physics.diagnostics_enabled());
+        self.physics = physics;
+        self.history.clear();
+        self.previous.clear();
+        self.scene_changed = true;
+        log::info!("Loaded {}", path);
+        return true;
+    }
+    
+    fn chain_ball(&mut self, location: Vec2<Sim>, fixed: bool) -> BallHandle
+    {
+        let (_, m) = Material::PRESETS[self.material];
+        let mut b = Ball::new_material(Ball::new(location, CHAIN_RADIUS as Sim, colour(200, 200, 200)), m);
+        b.fixed = fixed;
+        return self.physics.add(b);
+    }
+    
+    // Hangs a chain of balls from a fixed anchor at the cursor
+    fn spawn_chain(&mut self)
+    {
+        let step = vec2(0.0, -CHAIN_RADIUS as Sim * 2.0);
+        let mut prev = self.chain_ball(self.cursor(), true);
+        for i in 1..CHAIN_LENGTH
+        {
+            let id = self.chain_ball(self.cursor() + (step * i as Sim), false);
+            self.physics.connect(prev, id, LinkKind::Stick);
+            prev = id;
+        }
+    }
+    
+    // Strings a slightly slack rope bridge across the cursor, fixed at both ends
+    fn spawn_bridge(&mut self)
+    {
+        let spacing = CHAIN_RADIUS as Sim * 2.0;
+        let start = self.cursor() - vec2(spacing * (CHAIN_LENGTH / 2) as Sim, 0.0);
+        let mut prev = self.chain_ball(start, true);
+        for i in 1..CHAIN_LENGTH
+        {
+            let id = self.chain_ball(start + vec2(spacing * i as Sim, 0.0), i == CHAIN_LENGTH - 1);
+            self.physics.add_link(Link::new(prev, id, spacing * 1.05, LinkKind::Rope));
+            prev = id;
+        }
     }
 }
 
+// Balls are drawn alpha of the way from their previous location to their
+// current one, and new balls without a previous location where they are.
+// Balls in soft bodies are left out, as the bodies are drawn whole. Balls
+// over a wrapped edge are drawn again poking in from the other side.
+fn fill_buffer(balls: &Physics<Sim>, previous: &[Vec2<Sim>], alpha: f32, inst: &mut Vec<Instance>, drawn: &mut Vec<Vec2>, in_body: &mut Vec<bool>)
+{
+    let (periodic, bounds) = (balls.wrapping(), balls.get_bounds());
+    inst.clear();
+    drawn.clear();
+    in_body.clear();
+    in_body.resize(balls.count(), false);
+    for h in balls.bodies().iter().flat_map(|b| &b.members)
+    {
+        if let Some(i) = balls.index(*h)
+        {
+            in_body[i] = true;
+        }
+    }
+    
+    for (i, b) in balls.into_iter().enumerate()
+    {
+        let mut l = b.location;
+        if let Some(p) = previous.get(i)
+        {
+            // From wherever it was on this side of the seam
+            let p = p + periodic.image(b.location, *p, bounds);
+            l = p + ((b.location - p) * alpha as Sim);
+        }
+        let ghosts = periodic.ghosts(l, b.radius, bounds);
+        let l = cast2(l);
+        drawn.push(l);
+        if in_body[i] { continue; }
+        
+        let mut instance = Instance::from_ball(b);
+        instance.location = l;
+        inst.push(instance);
+        for g in ghosts
+        {
+            instance.location = l + cast2(g);
+            inst.push(instance);
+        }
+    }
+}
+
+// Each soft body filled in within its outline, which is pushed out from the
+// middle by the balls' radii to reach the edge of the balls. Like the balls,
+// bodies over a wrapped edge are drawn again poking in from the other side.
+fn body_mesh(physics: &Physics<Sim>, drawn: &[Vec2]) -> Mesh
+{
+    let mut mesh = Mesh::new();
+    if physics.bodies().is_empty() { return mesh; }
+    
+    let (periodic, bounds) = (physics.wrapping(), cast4(physics.get_bounds()));
+    for body in physics.bodies()
+    {
+        let edge: Vec<(Vec2, f32)> = body.outline.iter()
+            .filter_map(|&o| Some((drawn[physics.index(body.members[o])?], physics.get(body.members[o])?.radius.as_f32())))
+            .collect();
+        if edge.len() < 3 { continue; }
+        // Gathered around the first ball as the solver does, so a body
+        // straddling a seam is drawn as one piece
+        let first = edge[0].0;
+        let edge: Vec<(Vec2, f32)> = edge.iter().map(|&(p, r)| (p + periodic.image(first, p, bounds), r)).collect();
+        
+        // Each ball is pushed out square to its neighbours, so dents in the
+        // outline stay dents
+        let outward = if signed_area(&edge.iter().map(|(p, _)| *p).collect::<Vec<_>>()) >= 0.0 { 1.0 } else { -1.0 };
+        let n = edge.len();
+        let points: Vec<Vec2> = (0..n).map(|k|
+        {
+            let (p, r) = edge[k];
+            let d = edge[(k + 1) % n].0 - edge[(k + n - 1) % n].0;
+            let len = d.magnitude();
+            if len == 0.0 { p } else { p + (vec2(d.y, -d.x) * (outward * r / len)) }
+        }).collect();
+        mesh.add_outline(&points, c_to_v(body.colour));
+        
+        let centre = points.iter().fold(vec2(0.0, 0.0), |sum, p| sum + *p) / n as f32;
+        let reach = points.iter().fold(0.0, |far: f32, p| far.max((p - centre).magnitude()));
+        for g in periodic.ghosts(centre, reach, bounds)
+        {
+            let moved: Vec<Vec2> = points.iter().map(|p| p + g).collect();
+            mesh.add_outline(&moved, c_to_v(body.colour));
+        }
+    }
+    return mesh;
+}
+
+// Rolling graph of the diagnostics history, one line per quantity, each
+// scaled to fit between its own smallest and largest value
+fn graph_mesh(mesh: &mut Mesh, history: &History<Sim>)
+{
+    let corners = [GRAPH_POS, GRAPH_POS + vec2(GRAPH_SIZE.x, 0.0), GRAPH_POS + GRAPH_SIZE, GRAPH_POS + vec2(0.0, GRAPH_SIZE.y)];
+    mesh.add_loop(&corners, 1.0, c_to_v(GRAPH_FRAME));
+    if history.len() < 2 { return; }
+    
+    let series: [(Measure, Colour); 4] = [
+        (|d| d.energy().as_f32(), ENERGY_COLOUR),
+        (|d| d.kinetic.as_f32(), KINETIC_COLOUR),
+        (|d| d.momentum.magnitude().as_f32(), MOMENTUM_COLOUR),
+        (|d| d.max_penetration.as_f32(), PENETRATION_COLOUR)
+    ];
+    let dx = GRAPH_SIZE.x / (history.capacity() - 1) as f32;
+    for (f, c) in series
+    {
+        // Rounding noise on a steady value stays flat rather than filling the graph
+        let (lo, hi) = history.range(f);
+        let span = (hi - lo).max(lo.abs().max(hi.abs()) * 0.01);
+        let span = if span > 0.0 { span } else { 1.0 };
+        
+        let points: Vec<Vec2> = history.iter().enumerate()
+            .map(|(i, d)| GRAPH_POS + vec2(i as f32 * dx, (f(d) - lo) / span * GRAPH_SIZE.y))
+            .collect();
+        for w in points.windows(2)
+        {
+            mesh.add_line(w[0], w[1], 1.0, c_to_v(c));
+        }
+    }
+}
+
+// Obstacles and the container outline, which only change on user input
+fn scene_mesh(physics: &Physics<Sim>) -> Mesh
+{
+    let mut mesh = Mesh::new();
+    let c = c_to_v(OBSTACLE_COLOUR);
+    
+    let container = physics.get_container();
+    if !matches!(container, Container::Bounds)
+    {
+        let outline: Vec<Vec2> = container.outline(physics.get_bounds()).into_iter().map(cast2).collect();
+        mesh.add_loop(&outline, LINE_WIDTH, c);
+    }
+    
+    for o in physics.obstacles()
+    {
+        match o.shape()
+        {
+            Shape::Segment { a, b } => mesh.add_line(cast2(*a), cast2(*b), LINE_WIDTH, c),
+            Shape::Circle { centre, radius } => mesh.add_circle(cast2(*centre), radius.as_f32(), c),
+            Shape::Polygon(points) => mesh.add_polygon(&points.iter().map(|&p| cast2(p)).collect::<Vec<_>>(), c)
+        }
+    }
+    for f in physics.forces()
+    {
+        f.draw(&mut mesh);
+    }
+    return mesh;
+}
+
 #[inline(always)]
 fn text(str: String) -> OwnedText
 {
@@ -371,9 +1270,27 @@ fn text(str: String) -> OwnedText
         .with_scale(15.0)
         .with_color([1.0; 4]);
 }
+// Seed given as --seed <n> on the command line
+fn seed_arg() -> Option<u64>
+{
+    let mut args = std::env::args().skip_while(|a| a != "--seed").skip(1);
+    return args.next()?.parse().ok();
+}
+
+// The world the game starts with
+fn start_world(rand: &mut StdRng, bounds: Vec4<Sim>) -> Physics<Sim>
+{
+    let mut physics = Physics::new(bounds);
+    for _ in 0..START_BALLS
+    {
+        physics.add(ball(rand, bounds, Material::DEFAULT));
+    }
+    return physics;
+}
+
 #[inline(always)]
-fn ball<T: rand::Rng>(rand: &mut T, bounds: Vec4) -> Ball
+fn ball<T: rand::Rng>(rand: &mut T, bounds: Vec4<Sim>, material: Material) -> Ball<Sim>
 {
     let range = vec2(bounds.x, bounds.w)..vec2(bounds.y, bounds.z);
-    return Ball::random(rand, &range, 1.0..5.0);
+    return Ball::new_material(Ball::random(rand, &range, 1.0..5.0), material);
 }
@@ -0,0 +1,286 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::maths::*;
+use crate::snapshot::*;
+
+pub const REPLAY_VERSION: u32 = 1;
+
+const HEADER: &str = "rusty_balls_replay";
+
+// Something the user asked the world to do. Replays store these rather than
+// keys, so changing the key bindings doesn't break old replays.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action
+{
+    TiltLeft,
+    TiltRight,
+    FlipGravity,
+    ResetGravity,
+    NextContainer,
+    Chain,
+    Bridge,
+    ContactEvents,
+    Galton,
+    Clear,
+    Impulses,
+    NextMaterial,
+    Sleep,
+    NextTool,
+    Drag,
+    ClearForces,
+    Air,
+    Galaxy,
+    Collisions,
+    Ring,
+    Blob,
+    Ccd,
+    Periodic
+}
+
+impl Action
+{
+    const NAMES: [(Action, &'static str); 23] = [
+        (Action::TiltLeft, "tilt_left"),
+        (Action::TiltRight, "tilt_right"),
+        (Action::FlipGravity, "flip_gravity"),
+        (Action::ResetGravity, "reset_gravity"),
+        (Action::NextContainer, "next_container"),
+        (Action::Chain, "chain"),
+        (Action::Bridge, "bridge"),
+        (Action::ContactEvents, "contact_events"),
+        (Action::Galton, "galton"),
+        (Action::Clear, "clear"),
+        (Action::Impulses, "impulses"),
+        (Action::NextMaterial, "next_material"),
+        (Action::Sleep, "sleep"),
+        (Action::NextTool, "next_tool"),
+        (Action::Drag, "drag"),
+        (Action::ClearForces, "clear_forces"),
+        (Action::Air, "air"),
+        (Action::Galaxy, "galaxy"),
+        (Action::Collisions, "collisions"),
+        (Action::Ring, "ring"),
+        (Action::Blob, "blob"),
+        (Action::Ccd, "ccd"),
+        (Action::Periodic, "periodic")
+    ];
+
+    pub fn name(self) -> &'static str
+    {
+        return Action::NAMES.iter().find(|(a, _)| *a == self).unwrap().1;
+    }
+    pub fn from_name(name: &str) -> Option<Action>
+    {
+        return Action::NAMES.iter().find(|(_, n)| *n == name).map(|(a, _)| *a);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Button
+{
+    Left,
+    Right
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Input
+{
+    // Cursor position in world space
+    Cursor(Vec2),
+    Press(Button),
+    Release(Button),
+    // New window size
+    Resize(Vec2),
+    Action(Action)
+}
+
+// A session's inputs, each stamped with the step it was applied before.
+// Starting from a fresh world made with seed and bounds, feeding the inputs
+// back at the same steps reproduces the session exactly.
+pub struct Recording
+{
+    pub seed: u64,
+    pub bounds: Vec4,
+    pub inputs: Vec<(u64, Input)>,
+    // Step count and state hash when recording stopped
+    pub end: Option<(u64, u64)>
+}
+
+impl Recording
+{
+    pub fn new(seed: u64, bounds: Vec4) -> Recording
+    {
+        return Recording {
+            seed,
+            bounds,
+            inputs: Vec::new(),
+            end: None
+        };
+    }
+
+    pub fn push(&mut self, step: u64, input: Input)
+    {
+        self.inputs.push((step, input));
+    }
+
+    pub fn to_text(&self) -> String
+    {
+        let b = self.bounds;
+        let mut out = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(out, "{} {}", HEADER, REPLAY_VERSION);
+        let _ = writeln!(out, "seed {}", self.seed);
+        let _ = writeln!(out, "bounds {} {} {} {}", b.x, b.y, b.z, b.w);
+        for (step, input) in &self.inputs
+        {
+            let _ = match input
+            {
+                Input::Cursor(p) => writeln!(out, "{} cursor {} {}", step, p.x, p.y),
+                Input::Press(b) => writeln!(out, "{} press {}", step, button_name(*b)),
+                Input::Release(b) => writeln!(out, "{} release {}", step, button_name(*b)),
+                Input::Resize(s) => writeln!(out, "{} resize {} {}", step, s.x, s.y),
+                Input::Action(a) => writeln!(out, "{} action {}", step, a.name())
+            };
+        }
+        if let Some((step, hash)) = self.end
+        {
+            let _ = writeln!(out, "end {} {:016x}", step, hash);
+        }
+        return out;
+    }
+
+    pub fn from_text(text: &str) -> Result<Recording>
+    {
+        let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        let mut next = |what: &str| match lines.next()
+        {
+            Some((n, l)) => Ok((n + 1, l.split_whitespace().collect::<Vec<_>>())),
+            None => Err(SnapshotError::Malformed(format!("missing {}", what)))
+        };
+
+        let (_, header) = next("header")?;
+        if header.first() != Some(&HEADER) { return Err(SnapshotError::Malformed("not a replay".to_string())); }
+        let version: u32 = parse(1, header.get(1))?;
+        if version > REPLAY_VERSION { return Err(SnapshotError::Version(version)); }
+
+        let (n, seed) = next("seed")?;
+        let mut recording = Recording::new(parse(n, seed.get(1))?, vec4(0.0, 0.0, 0.0, 0.0));
+        let (n, bounds) = next("bounds")?;
+        for i in 0..4
+        {
+            recording.bounds[i] = parse(n, bounds.get(i + 1))?;
+        }
+
+        while let Ok((n, words)) = next("")
+        {
+            if words[0] == "end"
+            {
+                let hash = words.get(2).and_then(|h| u64::from_str_radix(h, 16).ok());
+                let Some(hash) = hash else { return Err(bad_line(n)); };
+                recording.end = Some((parse(n, words.get(1))?, hash));
+                continue;
+            }
+
+            let step = parse(n, words.first())?;
+            let input = match words.get(1).copied()
+            {
+                Some("cursor") => Input::Cursor(vec2(parse(n, words.get(2))?, parse(n, words.get(3))?)),
+                Some("press") => Input::Press(parse_button(n, words.get(2))?),
+                Some("release") => Input::Release(parse_button(n, words.get(2))?),
+                Some("resize") => Input::Resize(vec2(parse(n, words.get(2))?, parse(n, words.get(3))?)),
+                Some("action") => match words.get(2).and_then(|a| Action::from_name(a))
+                {
+                    Some(a) => Input::Action(a),
+                    None => return Err(bad_line(n))
+                },
+                _ => return Err(bad_line(n))
+            };
+            recording.push(step, input);
+        }
+
+        return Ok(recording);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()>
+    {
+        fs::write(path, self.to_text())?;
+        return Ok(());
+    }
+    pub fn load(path: &Path) -> Result<Recording>
+    {
+        return Recording::from_text(&fs::read_to_string(path)?);
+    }
+}
+
+// Hands back a recording's inputs as their steps come up
+pub struct Playback
+{
+    recording: Recording,
+    next: usize
+}
+
+impl Playback
+{
+    pub fn new(recording: Recording) -> Playback
+    {
+        return Playback {
+            recording,
+            next: 0
+        };
+    }
+
+    pub fn recording(&self) -> &Recording
+    {
+        return &self.recording;
+    }
+
+    // Inputs to apply before running the given step
+    pub fn take(&mut self, step: u64) -> Vec<Input>
+    {
+        let inputs = &self.recording.inputs;
+        let start = self.next;
+        while self.next < inputs.len() && inputs[self.next].0 <= step
+        {
+            self.next += 1;
+        }
+        return inputs[start..self.next].iter().map(|(_, i)| *i).collect();
+    }
+
+    // Whether every input has been given out and the recorded end reached
+    pub fn finished(&self, step: u64) -> bool
+    {
+        let end = self.recording.end.map_or(0, |(s, _)| s);
+        return self.next >= self.recording.inputs.len() && step >= end;
+    }
+}
+
+fn button_name(b: Button) -> &'static str
+{
+    return match b
+    {
+        Button::Left => "left",
+        Button::Right => "right"
+    };
+}
+
+fn parse_button(line: usize, word: Option<&&str>) -> Result<Button>
+{
+    return match word.copied()
+    {
+        Some("left") => Ok(Button::Left),
+        Some("right") => Ok(Button::Right),
+        _ => Err(bad_line(line))
+    };
+}
+
+fn parse<T: std::str::FromStr>(line: usize, word: Option<&&str>) -> Result<T>
+{
+    return word.and_then(|w| w.parse().ok()).ok_or_else(|| bad_line(line));
+}
+
+fn bad_line(line: usize) -> SnapshotError
+{
+    return SnapshotError::Malformed(format!("bad line {}", line));
+}
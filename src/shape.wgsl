@@ -0,0 +1,27 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) colour: vec3<f32>,
+};
+
+struct VertexOutput
+{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) colour: vec3<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> matrix: mat4x4<f32>;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput
+{
+    var out: VertexOutput;
+    out.colour = in.colour;
+    out.clip_position = matrix * vec4<f32>(in.position, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.colour, 1.0);
+}
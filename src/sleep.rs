@@ -0,0 +1,113 @@
+use crate::ball::*;
+use crate::maths::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SleepSettings
+{
+    // Balls moving slower than this between steps, in units per second,
+    // count as still
+    pub velocity: real,
+    // How many steps in a row a whole island must be still to fall asleep
+    pub steps: u32
+}
+
+impl Default for SleepSettings
+{
+    fn default() -> Self
+    {
+        return SleepSettings {
+            velocity: 20.0,
+            steps: 60
+        };
+    }
+}
+
+// Union find over dense ball indices, grouping balls that touch or are
+// linked into islands.
+pub struct Islands
+{
+    parent: Vec<u32>
+}
+
+impl Islands
+{
+    pub fn new(count: usize) -> Islands
+    {
+        return Islands {
+            parent: (0..count as u32).collect()
+        };
+    }
+
+    pub fn find(&mut self, mut i: usize) -> usize
+    {
+        while self.parent[i] as usize != i
+        {
+            // Path halving
+            let grand = self.parent[self.parent[i] as usize];
+            self.parent[i] = grand;
+            i = grand as usize;
+        }
+        return i;
+    }
+
+    pub fn union(&mut self, a: usize, b: usize)
+    {
+        let a = self.find(a);
+        let b = self.find(b);
+        // Lower index as root keeps the result independent of pair order
+        if a < b
+        {
+            self.parent[b] = a as u32;
+        }
+        else
+        {
+            self.parent[a] = b as u32;
+        }
+    }
+}
+
+// Counts still steps for every awake ball, then puts islands to sleep when
+// all their balls have been still long enough, and wakes every ball of an
+// island with anything still moving in it. Fixed balls don't join islands,
+// so a shared anchor doesn't tie separate piles together.
+pub fn update_sleep<F: Float>(balls: &mut [Ball<F>], pairs: &[(usize, usize)], settings: SleepSettings, dt: F)
+{
+    let max_dist = F::of(settings.velocity.into()) * dt;
+    for b in balls.iter_mut()
+    {
+        b.count_still(max_dist);
+    }
+
+    let mut islands = Islands::new(balls.len());
+    for &(i, j) in pairs
+    {
+        if balls[i].fixed || balls[j].fixed { continue; }
+        islands.union(i, j);
+    }
+
+    // Whether every ball of the island rooted at each index is ready
+    let mut ready = vec![true; balls.len()];
+    for (i, b) in balls.iter().enumerate()
+    {
+        if b.fixed { continue; }
+        if !b.is_asleep() && b.still_steps() < settings.steps
+        {
+            let root = islands.find(i);
+            ready[root] = false;
+        }
+    }
+
+    for (i, b) in balls.iter_mut().enumerate()
+    {
+        if b.fixed { continue; }
+        let root = islands.find(i);
+        if ready[root]
+        {
+            b.sleep();
+        }
+        else
+        {
+            b.wake();
+        }
+    }
+}
@@ -0,0 +1,806 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::SplitWhitespace;
+
+use crate::ball::*;
+use crate::constraint::*;
+use crate::container::*;
+use crate::force::*;
+use crate::material::*;
+use crate::maths::*;
+use crate::nbody::*;
+use crate::obstacle::*;
+use crate::periodic::*;
+use crate::physics::*;
+use crate::softbody::*;
+
+// Bumped whenever the layout changes. Newer versions are refused.
+pub const VERSION: u32 = 1;
+
+const TEXT_HEADER: &str = "rusty_balls";
+const BINARY_MAGIC: &[u8; 4] = b"RBAL";
+
+// Every word a snapshot can contain. Binary snapshots store the index into
+// this instead of the word itself.
+const WORDS: [&str; 23] = [
+    "bounds", "gravity", "impulses",
+    "container", "obstacles", "balls", "links",
+    "ball", "link", "auto", "mass",
+    "segment", "circle", "capsule", "polygon", "rect",
+    "drag", "gravitation", "collisions", "bodies", "body",
+    "ccd", "periodic"
+];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format
+{
+    // Readable and editable by hand
+    Text,
+    // Little endian floats as wide as the physics uses, much smaller and
+    // faster for big worlds
+    Binary
+}
+
+#[derive(Debug)]
+pub enum SnapshotError
+{
+    Io(io::Error),
+    // Doesn't start with either header
+    NotSnapshot,
+    // Written by a newer version
+    Version(u32),
+    Malformed(String)
+}
+
+impl fmt::Display for SnapshotError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        return match self
+        {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::NotSnapshot => write!(f, "not a snapshot"),
+            SnapshotError::Version(v) => write!(f, "snapshot version {} is newer than {}", v, VERSION),
+            SnapshotError::Malformed(m) => write!(f, "malformed snapshot: {}", m)
+        };
+    }
+}
+
+impl From<io::Error> for SnapshotError
+{
+    fn from(e: io::Error) -> Self
+    {
+        return SnapshotError::Io(e);
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SnapshotError>;
+
+fn malformed<T>(what: &str) -> Result<T>
+{
+    return Err(SnapshotError::Malformed(what.to_string()));
+}
+
+// Both formats are written and read through these, so they share one layout
+trait Writer
+{
+    fn word(&mut self, w: &str);
+    fn real<F: Float>(&mut self, x: F);
+    fn count(&mut self, n: usize);
+    fn byte(&mut self, b: u8);
+    // Ends a record, a line in the text format
+    fn end(&mut self);
+}
+
+trait Reader
+{
+    fn word(&mut self) -> Result<&'static str>;
+    fn real<F: Float>(&mut self) -> Result<F>;
+    fn count(&mut self) -> Result<usize>;
+    fn byte(&mut self) -> Result<u8>;
+}
+
+struct TextWriter
+{
+    out: String,
+    // Whether the current line has anything on it yet
+    started: bool
+}
+
+impl TextWriter
+{
+    fn push(&mut self, s: &str)
+    {
+        if self.started
+        {
+            self.out.push(' ');
+        }
+        self.out.push_str(s);
+        self.started = true;
+    }
+}
+
+impl Writer for TextWriter
+{
+    fn word(&mut self, w: &str)
+    {
+        self.push(w);
+    }
+    // Display prints the shortest form that parses back to the same bits
+    fn real<F: Float>(&mut self, x: F)
+    {
+        self.push(&x.to_string());
+    }
+    fn count(&mut self, n: usize)
+    {
+        self.push(&n.to_string());
+    }
+    fn byte(&mut self, b: u8)
+    {
+        self.push(&b.to_string());
+    }
+    fn end(&mut self)
+    {
+        self.out.push('\n');
+        self.started = false;
+    }
+}
+
+struct TextReader<'a>
+{
+    tokens: SplitWhitespace<'a>
+}
+
+impl<'a> TextReader<'a>
+{
+    fn token(&mut self) -> Result<&'a str>
+    {
+        return match self.tokens.next()
+        {
+            Some(t) => Ok(t),
+            None => malformed("unexpected end")
+        };
+    }
+}
+
+impl Reader for TextReader<'_>
+{
+    fn word(&mut self) -> Result<&'static str>
+    {
+        let t = self.token()?;
+        return match WORDS.iter().find(|&&w| w == t)
+        {
+            Some(w) => Ok(w),
+            None => malformed(&format!("unknown word {}", t))
+        };
+    }
+    fn real<F: Float>(&mut self) -> Result<F>
+    {
+        let t = self.token()?;
+        return t.parse().or_else(|_| malformed(&format!("{} isn't a number", t)));
+    }
+    fn count(&mut self) -> Result<usize>
+    {
+        let t = self.token()?;
+        return t.parse().or_else(|_| malformed(&format!("{} isn't a count", t)));
+    }
+    fn byte(&mut self) -> Result<u8>
+    {
+        let t = self.token()?;
+        return t.parse().or_else(|_| malformed(&format!("{} isn't a byte", t)));
+    }
+}
+
+struct BinaryWriter
+{
+    out: Vec<u8>,
+    // Bytes per float, 4 or 8
+    width: usize
+}
+
+impl Writer for BinaryWriter
+{
+    fn word(&mut self, w: &str)
+    {
+        let i = WORDS.iter().position(|&x| x == w).expect("word missing from WORDS");
+        self.out.push(i as u8);
+    }
+    fn real<F: Float>(&mut self, x: F)
+    {
+        if self.width == 4
+        {
+            self.out.extend_from_slice(&x.as_f32().to_le_bytes());
+            return;
+        }
+        self.out.extend_from_slice(&x.as_f64().to_le_bytes());
+    }
+    fn count(&mut self, n: usize)
+    {
+        self.out.extend_from_slice(&(n as u32).to_le_bytes());
+    }
+    fn byte(&mut self, b: u8)
+    {
+        self.out.push(b);
+    }
+    fn end(&mut self) {}
+}
+
+struct BinaryReader<'a>
+{
+    data: &'a [u8],
+    // Bytes per float the file was written with, converted to whatever
+    // the physics being read into uses
+    width: usize
+}
+
+impl BinaryReader<'_>
+{
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]>
+    {
+        if self.data.len() < N { return malformed("unexpected end"); }
+
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        return Ok(bytes.try_into().unwrap());
+    }
+}
+
+impl Reader for BinaryReader<'_>
+{
+    fn word(&mut self) -> Result<&'static str>
+    {
+        let [i] = self.take()?;
+        return match WORDS.get(i as usize)
+        {
+            Some(w) => Ok(w),
+            None => malformed(&format!("unknown word {}", i))
+        };
+    }
+    fn real<F: Float>(&mut self) -> Result<F>
+    {
+        if self.width == 4
+        {
+            return Ok(F::of(f32::from_le_bytes(self.take()?) as f64));
+        }
+        return Ok(F::of(f64::from_le_bytes(self.take()?)));
+    }
+    fn count(&mut self) -> Result<usize>
+    {
+        return Ok(u32::from_le_bytes(self.take()?) as usize);
+    }
+    fn byte(&mut self) -> Result<u8>
+    {
+        let [b] = self.take()?;
+        return Ok(b);
+    }
+}
+
+fn write_vec2<F: Float, W: Writer>(w: &mut W, v: Vec2<F>)
+{
+    w.real(v.x);
+    w.real(v.y);
+}
+fn read_vec2<F: Float, R: Reader>(r: &mut R) -> Result<Vec2<F>>
+{
+    return Ok(vec2(r.real()?, r.real()?));
+}
+
+fn write_points<F: Float, W: Writer>(w: &mut W, points: &[Vec2<F>])
+{
+    w.count(points.len());
+    for &p in points
+    {
+        write_vec2(w, p);
+    }
+}
+fn read_points<F: Float, R: Reader>(r: &mut R) -> Result<Vec<Vec2<F>>>
+{
+    let n = r.count()?;
+    return (0..n).map(|_| read_vec2(r)).collect();
+}
+
+fn expect<R: Reader>(r: &mut R, word: &str) -> Result<()>
+{
+    let w = r.word()?;
+    if w != word { return malformed(&format!("expected {}, found {}", word, w)); }
+
+    return Ok(());
+}
+
+fn write_drag<W: Writer>(w: &mut W, d: Drag)
+{
+    w.real(d.linear);
+    w.real(d.quadratic);
+    w.real(d.damping);
+}
+fn read_drag<R: Reader>(r: &mut R) -> Result<Drag>
+{
+    return Ok(Drag::new(r.real()?, r.real()?, r.real()?));
+}
+
+fn write_world<F: Float, W: Writer>(w: &mut W, physics: &Physics<F>)
+{
+    let bounds = physics.get_bounds();
+    w.word("bounds");
+    for i in 0..4
+    {
+        w.real(bounds[i]);
+    }
+    w.end();
+    w.word("gravity");
+    write_vec2(w, physics.get_gravity());
+    w.end();
+    w.word("impulses");
+    w.byte(physics.impulses() as u8);
+    w.end();
+    w.word("ccd");
+    w.byte(physics.ccd() as u8);
+    w.end();
+    let periodic = physics.periodic();
+    w.word("periodic");
+    w.byte(periodic.x as u8);
+    w.byte(periodic.y as u8);
+    w.end();
+    w.word("drag");
+    write_drag(w, physics.get_drag());
+    w.end();
+    w.word("gravitation");
+    match physics.gravitation()
+    {
+        None => w.byte(0),
+        Some(g) =>
+        {
+            match g.method
+            {
+                Method::Exact => w.byte(1),
+                Method::BarnesHut { theta } =>
+                {
+                    w.byte(2);
+                    w.real(theta);
+                }
+            }
+            w.real(g.constant);
+            w.real(g.softening);
+        }
+    }
+    w.end();
+    w.word("collisions");
+    w.byte(match physics.collisions()
+    {
+        Collisions::Resolve => 0,
+        Collisions::Merge => 1,
+        Collisions::Ignore => 2
+    });
+    w.end();
+
+    w.word("container");
+    match physics.get_container()
+    {
+        Container::Bounds => w.word("rect"),
+        Container::Circle { centre, radius } =>
+        {
+            w.word("circle");
+            write_vec2(w, *centre);
+            w.real(*radius);
+        }
+        Container::Capsule { a, b, radius } =>
+        {
+            w.word("capsule");
+            write_vec2(w, *a);
+            write_vec2(w, *b);
+            w.real(*radius);
+        }
+        Container::Polygon(points) =>
+        {
+            w.word("polygon");
+            write_points(w, points);
+        }
+    }
+    w.end();
+
+    w.word("obstacles");
+    w.count(physics.obstacles().len());
+    w.end();
+    for o in physics.obstacles()
+    {
+        match o.shape()
+        {
+            Shape::Segment { a, b } =>
+            {
+                w.word("segment");
+                write_vec2(w, *a);
+                write_vec2(w, *b);
+            }
+            Shape::Circle { centre, radius } =>
+            {
+                w.word("circle");
+                write_vec2(w, *centre);
+                w.real(*radius);
+            }
+            Shape::Polygon(points) =>
+            {
+                w.word("polygon");
+                write_points(w, points);
+            }
+        }
+        w.end();
+    }
+
+    // Links refer to balls by their position in the list
+    let mut index = HashMap::new();
+    w.word("balls");
+    w.count(physics.count());
+    w.end();
+    for (i, (h, b)) in physics.iter().enumerate()
+    {
+        index.insert(h, i);
+        w.word("ball");
+        write_vec2(w, b.location);
+        write_vec2(w, b.old_pos());
+        w.real(b.radius);
+        w.byte(b.colour.x);
+        w.byte(b.colour.y);
+        w.byte(b.colour.z);
+        w.real(b.material.restitution);
+        w.real(b.material.friction);
+        w.real(b.material.density);
+        match b.mass
+        {
+            Some(m) =>
+            {
+                w.word("mass");
+                w.real(m);
+            }
+            None => w.word("auto")
+        }
+        w.byte(b.fixed as u8);
+        match b.drag
+        {
+            Some(d) =>
+            {
+                w.word("drag");
+                write_drag(w, d);
+            }
+            None => w.word("auto")
+        }
+        w.end();
+    }
+
+    // Links and bodies should only ever join balls in the world, but any
+    // that don't are left out rather than written pointing at nothing
+    let links: Vec<(&Link<F>, usize, usize)> = physics.links().iter()
+        .filter_map(|l| Some((l, *index.get(&l.a)?, *index.get(&l.b)?)))
+        .collect();
+    let bodies: Vec<(&SoftBody<F>, Vec<usize>)> = physics.bodies().iter()
+        .filter_map(|body| Some((body, body.members.iter().map(|m| index.get(m).copied()).collect::<Option<Vec<usize>>>()?)))
+        .collect();
+
+    w.word("links");
+    w.count(links.len());
+    w.end();
+    for (l, a, b) in links
+    {
+        w.word("link");
+        w.count(a);
+        w.count(b);
+        w.real(l.length);
+        match l.kind
+        {
+            LinkKind::Stick => w.byte(0),
+            LinkKind::Rope => w.byte(1),
+            LinkKind::Spring { stiffness, damping } =>
+            {
+                w.byte(2);
+                w.real(stiffness);
+                w.real(damping);
+            }
+        }
+        w.end();
+    }
+
+    w.word("bodies");
+    w.count(bodies.len());
+    w.end();
+    for (body, members) in bodies
+    {
+        w.word("body");
+        w.count(members.len());
+        for (&m, q) in members.iter().zip(&body.rest)
+        {
+            w.count(m);
+            write_vec2(w, *q);
+        }
+        w.count(body.outline.len());
+        for &o in &body.outline
+        {
+            w.count(o);
+        }
+        w.real(body.stiffness);
+        w.byte(body.colour.x);
+        w.byte(body.colour.y);
+        w.byte(body.colour.z);
+        w.end();
+    }
+}
+
+fn read_world<F: Float, R: Reader>(r: &mut R) -> Result<Physics<F>>
+{
+    expect(r, "bounds")?;
+    let bounds = vec4(r.real()?, r.real()?, r.real()?, r.real()?);
+    let mut physics = Physics::new(bounds);
+    expect(r, "gravity")?;
+    physics.set_gravity(read_vec2(r)?);
+    expect(r, "impulses")?;
+    physics.set_impulses(r.byte()? != 0);
+    expect(r, "ccd")?;
+    physics.set_ccd(r.byte()? != 0);
+    expect(r, "periodic")?;
+    physics.set_periodic(Periodic::new(r.byte()? != 0, r.byte()? != 0));
+    expect(r, "drag")?;
+    physics.set_drag(read_drag(r)?);
+    expect(r, "gravitation")?;
+    let method = match r.byte()?
+    {
+        0 => None,
+        1 => Some(Method::Exact),
+        2 => Some(Method::BarnesHut { theta: r.real()? }),
+        m => return malformed(&format!("unknown gravitation {}", m))
+    };
+    if let Some(method) = method
+    {
+        physics.set_gravitation(Some(Gravitation::new(r.real()?, r.real()?, method)));
+    }
+    expect(r, "collisions")?;
+    let collisions = match r.byte()?
+    {
+        0 => Collisions::Resolve,
+        1 => Collisions::Merge,
+        2 => Collisions::Ignore,
+        c => return malformed(&format!("unknown collisions {}", c))
+    };
+    physics.set_collisions(collisions);
+
+    expect(r, "container")?;
+    let container = match r.word()?
+    {
+        "rect" => Container::Bounds,
+        "circle" => Container::Circle { centre: read_vec2(r)?, radius: r.real()? },
+        "capsule" => Container::Capsule { a: read_vec2(r)?, b: read_vec2(r)?, radius: r.real()? },
+        "polygon" => Container::Polygon(read_points(r)?),
+        w => return malformed(&format!("unknown container {}", w))
+    };
+    physics.set_container(container);
+
+    expect(r, "obstacles")?;
+    for _ in 0..r.count()?
+    {
+        let shape = match r.word()?
+        {
+            "segment" => Shape::Segment { a: read_vec2(r)?, b: read_vec2(r)? },
+            "circle" => Shape::Circle { centre: read_vec2(r)?, radius: r.real()? },
+            "polygon" => Shape::Polygon(read_points(r)?),
+            w => return malformed(&format!("unknown obstacle {}", w))
+        };
+        physics.add_obstacle(Obstacle::new(shape));
+    }
+
+    expect(r, "balls")?;
+    let count = r.count()?;
+    let mut handles = Vec::new();
+    for _ in 0..count
+    {
+        expect(r, "ball")?;
+        let location = read_vec2(r)?;
+        let old_pos = read_vec2(r)?;
+        let radius = r.real()?;
+        let colour = colour(r.byte()?, r.byte()?, r.byte()?);
+        let material = Material::new(r.real()?, r.real()?, r.real()?);
+        let mass = match r.word()?
+        {
+            "auto" => None,
+            "mass" => Some(r.real()?),
+            w => return malformed(&format!("expected mass, found {}", w))
+        };
+
+        let mut b = Ball::new_material(Ball::new(location, radius, colour), material);
+        b.set_old_pos(old_pos);
+        b.mass = mass;
+        b.fixed = r.byte()? != 0;
+        b.drag = match r.word()?
+        {
+            "auto" => None,
+            "drag" => Some(read_drag(r)?),
+            w => return malformed(&format!("expected drag, found {}", w))
+        };
+        handles.push(physics.add(b));
+    }
+
+    expect(r, "links")?;
+    for _ in 0..r.count()?
+    {
+        expect(r, "link")?;
+        let (Some(&a), Some(&b)) = (handles.get(r.count()?), handles.get(r.count()?))
+            else { return malformed("link to a missing ball"); };
+        let length = r.real()?;
+        let kind = match r.byte()?
+        {
+            0 => LinkKind::Stick,
+            1 => LinkKind::Rope,
+            2 => LinkKind::Spring { stiffness: r.real()?, damping: r.real()? },
+            k => return malformed(&format!("unknown link kind {}", k))
+        };
+        physics.add_link(Link::new(a, b, length, kind));
+    }
+
+    expect(r, "bodies")?;
+    for _ in 0..r.count()?
+    {
+        expect(r, "body")?;
+        let mut members = Vec::new();
+        let mut rest = Vec::new();
+        for _ in 0..r.count()?
+        {
+            let Some(&h) = handles.get(r.count()?) else { return malformed("body with a missing ball"); };
+            members.push(h);
+            rest.push(read_vec2(r)?);
+        }
+        let outline = (0..r.count()?).map(|_| r.count()).collect::<Result<Vec<usize>>>()?;
+        let stiffness = r.real()?;
+        let colour = colour(r.byte()?, r.byte()?, r.byte()?);
+        if !physics.add_body(SoftBody::new(members, rest, outline, stiffness, colour))
+        {
+            return malformed("bad body");
+        }
+    }
+
+    return Ok(physics);
+}
+
+// The balls, links, soft bodies, obstacles, container and world settings.
+// Thread count, sleeping and contact events are left for the caller to set
+// again, and every ball starts awake. Force generators can be any type, so
+// they aren't saved either.
+pub fn to_text<F: Float>(physics: &Physics<F>) -> String
+{
+    let mut w = TextWriter { out: String::new(), started: false };
+    w.word(TEXT_HEADER);
+    w.count(VERSION as usize);
+    w.end();
+    write_world(&mut w, physics);
+    return w.out;
+}
+
+pub fn from_text<F: Float>(text: &str) -> Result<Physics<F>>
+{
+    let mut tokens = text.split_whitespace();
+    if tokens.next() != Some(TEXT_HEADER) { return Err(SnapshotError::NotSnapshot); }
+
+    let mut r = TextReader { tokens };
+    let version = r.count()? as u32;
+    if version > VERSION { return Err(SnapshotError::Version(version)); }
+
+    return read_world(&mut r);
+}
+
+pub fn to_binary<F: Float>(physics: &Physics<F>) -> Vec<u8>
+{
+    let mut w = BinaryWriter { out: BINARY_MAGIC.to_vec(), width: F::BYTES };
+    w.count(VERSION as usize);
+    w.byte(F::BYTES as u8);
+    write_world(&mut w, physics);
+    return w.out;
+}
+
+pub fn from_binary<F: Float>(data: &[u8]) -> Result<Physics<F>>
+{
+    let Some(data) = data.strip_prefix(BINARY_MAGIC) else { return Err(SnapshotError::NotSnapshot); };
+
+    let mut r = BinaryReader { data, width: 4 };
+    let version = r.count()? as u32;
+    if version > VERSION { return Err(SnapshotError::Version(version)); }
+    r.width = match r.byte()?
+    {
+        4 => 4,
+        8 => 8,
+        w => return malformed(&format!("floats {} bytes wide", w))
+    };
+
+    return read_world(&mut r);
+}
+
+pub fn save<F: Float>(physics: &Physics<F>, path: &Path, format: Format) -> Result<()>
+{
+    match format
+    {
+        Format::Text => fs::write(path, to_text(physics))?,
+        Format::Binary => fs::write(path, to_binary(physics))?
+    }
+    return Ok(());
+}
+
+// Reads either format, telling them apart by the header
+pub fn load<F: Float>(path: &Path) -> Result<Physics<F>>
+{
+    let data = fs::read(path)?;
+    if data.starts_with(BINARY_MAGIC)
+    {
+        return from_binary(&data);
+    }
+
+    return match std::str::from_utf8(&data)
+    {
+        Ok(text) => from_text(text),
+        Err(_) => Err(SnapshotError::NotSnapshot)
+    };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // Something of everything a snapshot holds
+    fn world<F: Float>() -> Physics<F>
+    {
+        let v = |x: f64, y: f64| vec2(F::of(x), F::of(y));
+        let mut p = Physics::new(vec4(F::of(-200.0), F::of(200.0), F::of(150.0), F::of(-150.0)));
+        p.set_ccd(true);
+        p.set_periodic(Periodic::new(true, false));
+        p.set_drag(Drag::new(0.1, 0.01, 0.0));
+        p.set_gravitation(Some(Gravitation::new(50.0, 2.0, Method::BarnesHut { theta: 0.5 })));
+        p.set_container(Container::Circle { centre: v(0.0, 0.0), radius: F::of(140.0) });
+        p.add_obstacle(Obstacle::new(Shape::Segment { a: v(-50.0, 0.0), b: v(50.0, 10.0) }));
+
+        let mut handles = Vec::new();
+        for i in 0..10
+        {
+            let x = i as f64;
+            let mut b = Ball::new_material(Ball::new(v((x * 13.1) - 60.0, x * 7.3), F::of(3.0 + (x * 0.3)), colour(i * 20, 100, 200)),
+                Material::new(0.5, 0.2, 1.5));
+            b.set_old_pos(v((x * 13.1) - 60.5, x * 7.1));
+            b.mass = if i == 2 { Some(F::of(4.0)) } else { None };
+            b.fixed = i == 3;
+            b.drag = if i == 4 { Some(Drag::new(1.0, 0.0, 0.0)) } else { None };
+            handles.push(p.add(b));
+        }
+        p.add_link(Link::new(handles[0], handles[1], F::of(12.5), LinkKind::Spring { stiffness: 0.3, damping: 0.1 }));
+        p.add_link(Link::new(handles[1], handles[2], F::of(20.0), LinkKind::Rope));
+        p.add_link(Link::new(handles[5], handles[6], F::of(14.0), LinkKind::Stick));
+
+        let (points, outline) = SoftBody::ring(F::of(20.0), F::of(3.0));
+        let members = points.iter().map(|&q| p.add(Ball::new(q + v(60.0, -60.0), F::of(3.0), colour(200, 80, 80)))).collect();
+        p.connect_body(members, outline, 0.5, colour(200, 80, 80));
+        return p;
+    }
+
+    #[test]
+    fn text_round_trips()
+    {
+        let text = to_text(&world::<real>());
+        assert_eq!(to_text(&from_text::<real>(&text).unwrap()), text);
+    }
+
+    #[test]
+    fn binary_round_trips()
+    {
+        let data = to_binary(&world::<real>());
+        assert_eq!(to_binary(&from_binary::<real>(&data).unwrap()), data);
+    }
+
+    #[test]
+    fn f32_snapshots_load_into_f64()
+    {
+        let narrow = world::<f32>();
+
+        // Every f32 is exactly an f64, so going back down loses nothing
+        let data = to_binary(&narrow);
+        let wide: Physics<f64> = from_binary(&data).unwrap();
+        assert_eq!(to_binary(&from_binary::<f32>(&to_binary(&wide)).unwrap()), data);
+
+        let text = to_text(&narrow);
+        assert_eq!(to_text(&from_text::<f64>(&text).unwrap()), text);
+    }
+}
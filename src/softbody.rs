@@ -0,0 +1,133 @@
+use cgmath::InnerSpace;
+use std::f64::consts::PI;
+
+use crate::ball::*;
+use crate::handle::*;
+use crate::maths::*;
+
+// A deformable blob made of balls. Every step the balls are pulled part of
+// the way back towards their rest shape, moved and turned to best fit where
+// they are now, so the blob can squash and spin but keeps its shape.
+#[derive(Clone, Debug)]
+pub struct SoftBody<F: Float = real>
+{
+    pub members: Vec<BallHandle>,
+    // Where each member sits relative to the centre of mass at rest
+    pub rest: Vec<Vec2<F>>,
+    // Members around the edge, in counter-clockwise order, as indices into
+    // members. Only used for drawing.
+    pub outline: Vec<usize>,
+    // Fraction of the way back to the rest shape each step, from 0 to 1
+    pub stiffness: real,
+    pub colour: Colour
+}
+
+impl<F: Float> SoftBody<F>
+{
+    pub fn new(members: Vec<BallHandle>, rest: Vec<Vec2<F>>, outline: Vec<usize>, stiffness: real, colour: Colour) -> SoftBody<F>
+    {
+        return SoftBody {
+            members,
+            rest,
+            outline,
+            stiffness,
+            colour
+        };
+    }
+
+    // Makes the rest shape the given locations, around their centre of mass
+    pub fn set_rest(&mut self, locations: &[Vec2<F>], masses: &[F])
+    {
+        let centre = centre_of_mass(locations, masses);
+        self.rest = locations.iter().map(|l| l - centre).collect();
+    }
+
+    // Takes the ball out of the body, keeping the rest of the shape
+    pub fn remove(&mut self, h: BallHandle) -> bool
+    {
+        let Some(i) = self.members.iter().position(|&m| m == h) else { return false; };
+
+        self.members.remove(i);
+        self.rest.remove(i);
+        self.outline.retain(|&o| o != i);
+        for o in &mut self.outline
+        {
+            if *o > i { *o -= 1; }
+        }
+        return true;
+    }
+
+    // Pulls the balls at indices towards the rest shape, which they are
+    // listed in the same order as
+    pub fn solve(&self, balls: &mut [Ball<F>], indices: &[usize])
+    {
+        let locations: Vec<Vec2<F>> = indices.iter().map(|&i| balls[i].location).collect();
+        let masses: Vec<F> = indices.iter().map(|&i| balls[i].mass()).collect();
+        let centre = centre_of_mass(&locations, &masses);
+
+        // Best fit rotation from rest, the 2D polar decomposition
+        let (mut cos, mut sin) = (F::zero(), F::zero());
+        for ((l, q), m) in locations.iter().zip(&self.rest).zip(&masses)
+        {
+            let p = l - centre;
+            cos += *m * q.dot(p);
+            sin += *m * ((q.x * p.y) - (q.y * p.x));
+        }
+        let angle = sin.atan2(cos);
+
+        for (&i, q) in indices.iter().zip(&self.rest)
+        {
+            let b = &mut balls[i];
+            if b.fixed { continue; }
+
+            let goal = centre + rotate(*q, angle);
+            b.location += (goal - b.location) * F::of(self.stiffness.into());
+        }
+    }
+
+    // Rest layout of a ring of touching balls around the origin, and its outline
+    pub fn ring(radius: F, ball_radius: F) -> (Vec<Vec2<F>>, Vec<usize>)
+    {
+        let n = ((F::of(PI) * radius / ball_radius).floor().as_f64() as usize).max(3);
+        let points = circle_points(vec2(F::zero(), F::zero()), radius, n);
+        return (points, (0..n).collect());
+    }
+
+    // Rest layout of a disc packed with touching balls around the origin,
+    // and the outline of its outer ring
+    pub fn disc(radius: F, ball_radius: F) -> (Vec<Vec2<F>>, Vec<usize>)
+    {
+        let spacing = ball_radius * F::of(2.0);
+        let row = spacing * F::of(0.75).sqrt();
+        let reach = radius - ball_radius;
+        let mut points = Vec::new();
+
+        let rows = (reach / row).floor().as_f64() as i32;
+        for y in -rows..=rows
+        {
+            let offset = if y % 2 == 0 { F::zero() } else { ball_radius };
+            let columns = (reach / spacing).ceil().as_f64() as i32 + 1;
+            for x in -columns..=columns
+            {
+                let p = vec2((F::of(x as f64) * spacing) + offset, F::of(y as f64) * row);
+                if p.magnitude() <= reach { points.push(p); }
+            }
+        }
+
+        // Anything within a ball of the edge is on the outline, sorted by angle
+        let mut outline: Vec<usize> = (0..points.len())
+            .filter(|&i| points[i].magnitude() > reach - spacing)
+            .collect();
+        outline.sort_by(|&a, &b| points[a].y.atan2(points[a].x).total_cmp(&points[b].y.atan2(points[b].x)));
+        return (points, outline);
+    }
+}
+
+fn centre_of_mass<F: Float>(locations: &[Vec2<F>], masses: &[F]) -> Vec2<F>
+{
+    let total = masses.iter().fold(F::zero(), |t, &m| t + m);
+    if total == F::zero() { return vec2(F::zero(), F::zero()); }
+
+    let sum = locations.iter().zip(masses).fold(vec2(F::zero(), F::zero()), |s, (l, m)| s + (l * *m));
+    return sum / total;
+}
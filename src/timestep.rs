@@ -0,0 +1,55 @@
+use crate::maths::*;
+
+// Fixed timestep accumulator. Real frame time is banked and spent in whole
+// steps, so the simulation speed doesn't depend on the frame rate.
+pub struct Timestep
+{
+    step: real,
+    max_steps: u32,
+    accumulator: real
+}
+
+impl Timestep
+{
+    pub fn new(step: real, max_steps: u32) -> Timestep
+    {
+        return Timestep {
+            step,
+            max_steps,
+            accumulator: 0.0
+        };
+    }
+
+    // Banks frame_time seconds and returns how many steps to run. Past
+    // max_steps the extra time is dropped rather than carried over, so slow
+    // frames can't snowball into ever slower ones.
+    pub fn advance(&mut self, frame_time: real) -> u32
+    {
+        self.accumulator += frame_time;
+        let mut steps = (self.accumulator / self.step) as u32;
+
+        if steps > self.max_steps
+        {
+            steps = self.max_steps;
+            self.accumulator %= self.step;
+        }
+        else
+        {
+            self.accumulator -= steps as real * self.step;
+        }
+
+        return steps;
+    }
+
+    pub fn step(&self) -> real
+    {
+        return self.step;
+    }
+
+    // How far between the last step and the next the current time is, from
+    // 0 to 1, for interpolating what gets drawn
+    pub fn alpha(&self) -> real
+    {
+        return (self.accumulator / self.step).clamp(0.0, 1.0);
+    }
+}